@@ -1,6 +1,10 @@
+use std::error::Error;
+use std::fmt;
+use std::mem;
 use std::os::raw::{c_char, c_int};
-use wren_sys::WrenErrorType;
+use wren_sys::{WrenErrorType, WrenInterpretResult};
 use util::*;
+use vm::Handle;
 
 #[derive(Debug)]
 pub struct Trace {
@@ -19,33 +23,241 @@ impl Trace {
     }
 }
 
+/// A single compile error.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub module: String,
+    pub line: u32,
+    pub message: String,
+}
+
 #[derive(Debug)]
 pub enum WrenError {
+    /// Every compile error reported for the source, in order.
     Compile {
-        module: String,
-        line: u32,
+        errors: Vec<Diagnostic>,
+        stray: Vec<Trace>,
+    },
+    Runtime {
         message: String,
+        stack: Vec<Trace>,
+        stray: Vec<Trace>,
+        /// Whether the error was raised by a panic in a foreign method.
+        panicked: bool,
+    },
+    Arity {
+        signature: String,
+        expected: usize,
+        got: usize,
     },
-    Runtime { message: String, stack: Vec<Trace> },
+    Conversion(String),
+    InvalidHandle,
+    UnknownVariable { module: String, name: String },
+    /// The VM went past the limit set with `WrenBuilder::memory_limit`.
+    OutOfMemory { limit: usize },
+    /// `Wren::interpret_module` was given a module that is already loaded.
+    DuplicateModule(String),
+    /// `Wren::make_call` was given a signature Wren can't take.
+    InvalidSignature(String),
+    /// `WrenBuilder::bind_closure` was called for more methods than it
+    /// supports.
+    TooManyClosures { limit: usize },
+}
+
+/// Assembles the reports Wren makes through its `errorFn` while running a
+/// single interpret or call into one `WrenError`.
+///
+/// Reports are accepted in any order. Stack frames that don't follow a
+/// runtime error are kept as stray frames rather than dropped.
+#[derive(Debug, Default)]
+pub(crate) struct ErrorAccumulator {
+    compile: Vec<Diagnostic>,
+    runtime: Option<(String, Vec<Trace>)>,
+    runtime_panicked: bool,
+    // Whether the runtime error is Wren failing an import because the
+    // module didn't compile.
+    runtime_failed_import: bool,
+    // The module Wren was last handed the source of.
+    importing: Option<String>,
+    stray: Vec<Trace>,
+    // The error string of the latest fiber aborted by a panic. The handle
+    // keeps it alive, so no other string can take its address.
+    panic: Option<(*const c_char, Handle)>,
 }
 
-impl WrenError {
-    pub(crate) unsafe fn new(
+impl ErrorAccumulator {
+    /// Notes that a foreign method aborted its fiber with the string `text`
+    /// because of a panic.
+    pub(crate) fn record_panic(&mut self, text: *const c_char, handle: Handle) {
+        self.panic = Some((text, handle));
+    }
+
+    /// Notes that Wren is about to compile the module `name` for an import.
+    pub(crate) fn importing(&mut self, name: &str) {
+        self.importing = Some(name.to_string());
+    }
+
+    pub(crate) unsafe fn report(
+        &mut self,
         ty: WrenErrorType,
         module: *const c_char,
         line: c_int,
         message: *const c_char,
-    ) -> WrenError {
+    ) {
         match ty {
-            WrenErrorType::WREN_ERROR_COMPILE => WrenError::Compile {
+            WrenErrorType::WREN_ERROR_COMPILE => self.compile.push(Diagnostic {
                 module: lossy_string(module),
                 line: line as u32,
                 message: lossy_string(message),
-            },
-            _ => WrenError::Runtime {
-                message: lossy_string(message),
-                stack: vec![],
-            },
+            }),
+            WrenErrorType::WREN_ERROR_RUNTIME => {
+                if let Some((_, stack)) = self.runtime.take() {
+                    self.stray.extend(stack);
+                }
+                // Wren reports the error string itself, so the run ended
+                // because of the panic only if it is the very same string.
+                // One caught by `Fiber.try` and raised again as a copy
+                // doesn't count.
+                self.runtime_panicked = matches!(self.panic, Some((text, _)) if text == message);
+                let message = lossy_string(message);
+                // An import whose module reported compile errors fails with
+                // a runtime error straight away. If a script catches that
+                // one, it isn't reported, so the message has to be Wren's
+                // for that very module too.
+                self.runtime_failed_import = match self.importing.take() {
+                    Some(module) => {
+                        self.compile.iter().any(|error| error.module == module)
+                            && message == format!("Could not compile module '{}'.", module)
+                    }
+                    None => false,
+                };
+                self.runtime = Some((message, vec![]));
+            }
+            WrenErrorType::WREN_ERROR_STACK_TRACE => {
+                let trace = Trace::new(message, module, line);
+                match self.runtime {
+                    Some((_, ref mut stack)) => stack.push(trace),
+                    None => self.stray.push(trace),
+                }
+            }
         }
     }
+
+    pub(crate) fn clear(&mut self) {
+        *self = Default::default();
+    }
+
+    /// Turns everything reported so far into the error for `result`, leaving
+    /// the accumulator empty for the next run.
+    pub(crate) fn finish(&mut self, result: WrenInterpretResult) -> Result<(), WrenError> {
+        let ErrorAccumulator {
+            compile,
+            runtime,
+            runtime_panicked,
+            runtime_failed_import,
+            mut stray,
+            ..
+        } = mem::take(self);
+        // An imported module that doesn't compile fails the import at
+        // runtime, but the compile errors are what matter.
+        let result = if runtime.is_some() && runtime_failed_import {
+            WrenInterpretResult::WREN_RESULT_COMPILE_ERROR
+        } else {
+            result
+        };
+        match result {
+            WrenInterpretResult::WREN_RESULT_SUCCESS => Ok(()),
+            WrenInterpretResult::WREN_RESULT_COMPILE_ERROR => {
+                if let Some((_, stack)) = runtime {
+                    stray.extend(stack);
+                }
+                Err(WrenError::Compile {
+                    errors: compile,
+                    stray,
+                })
+            }
+            WrenInterpretResult::WREN_RESULT_RUNTIME_ERROR => {
+                let (message, stack) = match runtime {
+                    Some(runtime) => runtime,
+                    None => ("unknown runtime error".into(), vec![]),
+                };
+                Err(WrenError::Runtime {
+                    message,
+                    stack,
+                    stray,
+                    panicked: runtime_panicked,
+                })
+            }
+        }
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({}:{})", self.function, self.module, self.line)
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.module, self.line, self.message)
+    }
 }
+
+impl fmt::Display for WrenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WrenError::Compile { ref errors, .. } => {
+                for (i, error) in errors.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{}", error)?;
+                }
+                Ok(())
+            }
+            WrenError::Runtime {
+                ref message,
+                ref stack,
+                ..
+            } => {
+                write!(f, "{}", message)?;
+                for trace in stack {
+                    write!(f, "\n    at {}", trace)?;
+                }
+                Ok(())
+            }
+            WrenError::Arity {
+                ref signature,
+                expected,
+                got,
+            } => write!(
+                f,
+                "{} expects {} argument(s), got {}",
+                signature,
+                expected,
+                got
+            ),
+            WrenError::Conversion(ref message) => write!(f, "{}", message),
+            WrenError::InvalidHandle => write!(f, "handle does not belong to this VM"),
+            WrenError::UnknownVariable {
+                ref module,
+                ref name,
+            } => write!(f, "no variable {} in module \"{}\"", name, module),
+            WrenError::OutOfMemory { limit } => {
+                write!(f, "out of memory (limit is {} bytes)", limit)
+            }
+            WrenError::DuplicateModule(ref module) => {
+                write!(f, "module \"{}\" is already loaded", module)
+            }
+            WrenError::InvalidSignature(ref signature) => {
+                write!(f, "invalid method signature {:?}", signature)
+            }
+            WrenError::TooManyClosures { limit } => {
+                write!(f, "at most {} closures can be bound to a VM", limit)
+            }
+        }
+    }
+}
+
+impl Error for WrenError {}
@@ -1,10 +1,23 @@
+use closures::{BoxedMethod, TRAMPOLINES};
+use errors::WrenError;
+use memory;
 use util::*;
-use std::os::raw::{c_char, c_void};
+use std::os::raw::{c_char, c_int, c_void};
 use wren_sys;
-use std::collections::HashMap;
-use std::ffi::CString;
+use wren_sys::WrenType;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::ffi::{CStr, CString};
+use std::any::{Any, TypeId};
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::panic::{self, AssertUnwindSafe};
+use std::mem::size_of;
+use std::ptr;
 
-#[derive(Debug, Eq, PartialEq, Hash)]
+pub use thrush_derive::WrenClass;
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct MethodDesc {
     pub module: CString,
     pub class_name: CString,
@@ -13,7 +26,7 @@ pub struct MethodDesc {
 }
 
 #[doc(hidden)]
-#[derive(Debug, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct ClassDesc {
     pub module: CString,
     pub class_name: CString,
@@ -23,6 +36,420 @@ pub struct ClassDesc {
 pub struct Foreign {
     pub classes: HashMap<ClassDesc, wren_sys::WrenForeignClassMethods>,
     pub methods: HashMap<MethodDesc, wren_sys::WrenForeignMethodFn>,
+    pub constructors: HashMap<ClassDesc, &'static [&'static str]>,
+    pub(crate) closures: Vec<BoxedMethod>,
+    closure_index: HashMap<MethodDesc, usize>,
+    declared: RefCell<HashSet<ClassDesc>>,
+}
+
+/// The slot array of a VM, either the arguments and return value of a
+/// foreign method or the receiver and arguments of a call from the host.
+pub struct Slots<'vm> {
+    vm: *mut wren_sys::WrenVM,
+    // Scratch slots of nested list conversions, see `scratch`.
+    scratch: usize,
+    depth: usize,
+    claimed: usize,
+    _vm: PhantomData<&'vm mut wren_sys::WrenVM>,
+}
+
+impl<'vm> Slots<'vm> {
+    #[doc(hidden)]
+    pub unsafe fn new(vm: *mut wren_sys::WrenVM) -> Slots<'vm> {
+        Slots {
+            vm,
+            scratch: 0,
+            depth: 0,
+            claimed: 0,
+            _vm: PhantomData,
+        }
+    }
+
+    pub fn raw(&self) -> *mut wren_sys::WrenVM {
+        self.vm
+    }
+
+    pub fn count(&self) -> usize {
+        unsafe { wren_sys::wrenGetSlotCount(self.vm) as usize }
+    }
+
+    pub fn ensure(&mut self, count: usize) {
+        unsafe { wren_sys::wrenEnsureSlots(self.vm, count as c_int) }
+    }
+
+    pub fn slot_type(&self, slot: usize) -> Result<WrenType, String> {
+        let slot = self.check(slot)?;
+        Ok(unsafe { wren_sys::wrenGetSlotType(self.vm, slot) })
+    }
+
+    pub fn get<T: FromSlot>(&mut self, slot: usize) -> Result<T, String> {
+        T::from_slot(self, slot)
+    }
+
+    pub fn set<T: IntoSlot>(&mut self, slot: usize, value: T) -> Result<(), String> {
+        value.into_slot(self, slot)
+    }
+
+    /// Aborts the current fiber with `message`. The foreign method should
+    /// return right after.
+    pub fn abort(&mut self, message: &str) {
+        let message = CString::new(message.replace('\0', "\\0")).unwrap();
+        unsafe {
+            wren_sys::wrenSetSlotString(self.vm, 0, message.as_ptr());
+            wren_sys::wrenAbortFiber(self.vm, 0);
+        }
+    }
+
+    /// Borrows the Rust value of the foreign object in `slot`.
+    ///
+    /// Borrows are checked at runtime like with a `RefCell`, since scripts
+    /// can pass the same object several times. The object is kept alive
+    /// for as long as it is borrowed, even if `slot` is overwritten.
+    pub fn borrow<T: WrenClass>(&mut self, slot: usize) -> Result<ForeignRef<'vm, T>, String> {
+        let object = self.foreign_object::<T>(slot)?;
+        let borrow = unsafe { &(*object).borrow };
+        if borrow.get() < 0 {
+            return Err(format!("{} is already mutably borrowed", T::CLASS));
+        }
+        borrow.set(borrow.get() + 1);
+        Ok(ForeignRef {
+            object,
+            _root: self.root(slot),
+            _vm: PhantomData,
+        })
+    }
+
+    /// Mutably borrows the Rust value of the foreign object in `slot`.
+    pub fn borrow_mut<T: WrenClass>(&mut self, slot: usize) -> Result<ForeignRefMut<'vm, T>, String> {
+        let object = self.foreign_object::<T>(slot)?;
+        let borrow = unsafe { &(*object).borrow };
+        if borrow.get() != 0 {
+            return Err(format!("{} is already borrowed", T::CLASS));
+        }
+        borrow.set(-1);
+        Ok(ForeignRefMut {
+            object,
+            _root: self.root(slot),
+            _vm: PhantomData,
+        })
+    }
+
+    /// The context given to `WrenBuilder::with_context`. Asking for the
+    /// wrong type is an error rather than a bad cast.
+    pub fn context<C: 'static>(&self) -> Result<&C, String> {
+        let context = unsafe { &(*self.user_data()).context };
+        let context = context.as_ref().ok_or_else(no_context)?;
+        context.downcast_ref().ok_or_else(wrong_context)
+    }
+
+    pub fn context_mut<C: 'static>(&mut self) -> Result<&mut C, String> {
+        let context = unsafe { &mut (*self.user_data()).context };
+        let context = context.as_mut().ok_or_else(no_context)?;
+        context.downcast_mut().ok_or_else(wrong_context)
+    }
+
+    // Other fields may be borrowed while a foreign method runs, such as the
+    // closure being called, so only the fields needed are ever borrowed.
+    fn user_data(&self) -> *mut ::vm::UserData {
+        unsafe { wren_sys::wrenGetUserData(self.vm) as *mut ::vm::UserData }
+    }
+
+    fn root(&mut self, slot: usize) -> Root {
+        Root {
+            vm: self.vm,
+            handle: unsafe { wren_sys::wrenGetSlotHandle(self.vm, slot as c_int) },
+        }
+    }
+
+    fn foreign_object<T: WrenClass>(&mut self, slot: usize) -> Result<*mut ForeignClass<T>, String> {
+        let index = self.expect(slot, WrenType::WREN_TYPE_FOREIGN, T::CLASS)?;
+        unsafe {
+            let ptr = wren_sys::wrenGetSlotForeign(self.vm, index) as *mut ForeignClass<T>;
+            if !ForeignClass::<T>::is_valid(ptr) {
+                return Err("attempt to use unbound foreign class".into());
+            }
+            if (*ptr).get_id() != TypeId::of::<T>() {
+                return Err(format!("foreign object with invalid type id for {}", T::CLASS));
+            }
+            Ok(ptr)
+        }
+    }
+
+    // The raw bytes of the string in `slot`, which need not be UTF-8.
+    pub(crate) fn bytes(&self, slot: usize) -> Result<Vec<u8>, String> {
+        let slot = self.expect(slot, WrenType::WREN_TYPE_STRING, "String")?;
+        let mut length: c_int = 0;
+        unsafe {
+            let bytes = wren_sys::wrenGetSlotBytes(self.vm, slot, &mut length);
+            Ok(::std::slice::from_raw_parts(bytes as *const u8, length as usize).to_vec())
+        }
+    }
+
+    // Runs `body` with a slot past the ones in use to hold list elements in.
+    // Each level of nesting gets one slot, shared by all the lists at that
+    // level. Wren can't give slots back, so the slots are reused by later
+    // conversions too, unless something else has claimed slots since.
+    pub(crate) fn scratch<T, F>(&mut self, body: F) -> T
+    where
+        F: FnOnce(&mut Slots<'vm>, usize) -> T,
+    {
+        if self.depth == 0 && self.count() != self.claimed {
+            self.scratch = self.count();
+        }
+        let slot = self.scratch + self.depth;
+        self.ensure(slot + 1);
+        self.claimed = self.count();
+        self.depth += 1;
+        let result = body(self, slot);
+        self.depth -= 1;
+        result
+    }
+
+    pub(crate) fn check(&self, slot: usize) -> Result<c_int, String> {
+        let count = self.count();
+        if slot >= count {
+            return Err(format!("slot {} out of range, only {} available", slot, count));
+        }
+        Ok(slot as c_int)
+    }
+
+    pub(crate) fn expect(&self, slot: usize, ty: WrenType, rust: &str) -> Result<c_int, String> {
+        match self.slot_type(slot)? {
+            t if t == ty => Ok(slot as c_int),
+            t => Err(format!("expecting {} for slot {}, got {:?}.", rust, slot, t)),
+        }
+    }
+}
+
+fn no_context() -> String {
+    "the VM has no context".into()
+}
+
+fn wrong_context() -> String {
+    "the VM context has a different type".into()
+}
+
+/// Conversion out of a slot into a Rust value.
+///
+/// The value can't borrow from the slot, which is why strings are read as
+/// `String` and there is no implementation for `&str`. `wren_fn!` and
+/// `#[thrush::methods]` still take `&str` parameters, by reading a `String`.
+pub trait FromSlot: Sized {
+    fn from_slot(slots: &mut Slots, slot: usize) -> Result<Self, String>;
+}
+
+/// Conversion of a Rust value into a slot.
+pub trait IntoSlot {
+    fn into_slot(self, slots: &mut Slots, slot: usize) -> Result<(), String>;
+}
+
+impl FromSlot for () {
+    fn from_slot(_: &mut Slots, _: usize) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl IntoSlot for () {
+    fn into_slot(self, slots: &mut Slots, slot: usize) -> Result<(), String> {
+        let slot = slots.check(slot)?;
+        unsafe { wren_sys::wrenSetSlotNull(slots.vm, slot) };
+        Ok(())
+    }
+}
+
+impl FromSlot for bool {
+    fn from_slot(slots: &mut Slots, slot: usize) -> Result<bool, String> {
+        let slot = slots.expect(slot, WrenType::WREN_TYPE_BOOL, "bool")?;
+        Ok(unsafe { wren_sys::wrenGetSlotBool(slots.vm, slot) })
+    }
+}
+
+impl IntoSlot for bool {
+    fn into_slot(self, slots: &mut Slots, slot: usize) -> Result<(), String> {
+        let slot = slots.check(slot)?;
+        unsafe { wren_sys::wrenSetSlotBool(slots.vm, slot, self) };
+        Ok(())
+    }
+}
+
+impl FromSlot for f64 {
+    fn from_slot(slots: &mut Slots, slot: usize) -> Result<f64, String> {
+        let slot = slots.expect(slot, WrenType::WREN_TYPE_NUM, "f64")?;
+        Ok(unsafe { wren_sys::wrenGetSlotDouble(slots.vm, slot) })
+    }
+}
+
+impl IntoSlot for f64 {
+    fn into_slot(self, slots: &mut Slots, slot: usize) -> Result<(), String> {
+        let slot = slots.check(slot)?;
+        unsafe { wren_sys::wrenSetSlotDouble(slots.vm, slot, self) };
+        Ok(())
+    }
+}
+
+impl FromSlot for String {
+    fn from_slot(slots: &mut Slots, slot: usize) -> Result<String, String> {
+        let slot = slots.expect(slot, WrenType::WREN_TYPE_STRING, "String")?;
+        Ok(unsafe { CStr::from_ptr(wren_sys::wrenGetSlotString(slots.vm, slot)) }
+            .to_string_lossy()
+            .into())
+    }
+}
+
+impl<'a> IntoSlot for &'a str {
+    fn into_slot(self, slots: &mut Slots, slot: usize) -> Result<(), String> {
+        let slot = slots.check(slot)?;
+        let out = CString::new(self).map_err(|e| format!("cstring convert error: {}", e))?;
+        unsafe { wren_sys::wrenSetSlotString(slots.vm, slot, out.as_ptr()) };
+        Ok(())
+    }
+}
+
+impl IntoSlot for String {
+    fn into_slot(self, slots: &mut Slots, slot: usize) -> Result<(), String> {
+        self.as_str().into_slot(slots, slot)
+    }
+}
+
+// Integers past 2^53 can't all be told apart as Wren numbers, so they're
+// refused in both directions rather than rounded.
+pub(crate) const MAX_EXACT: f64 = 9_007_199_254_740_992.0;
+
+macro_rules! impl_int_slot {
+    ($($ty:ident),*) => ($(
+        impl FromSlot for $ty {
+            fn from_slot(slots: &mut Slots, slot: usize) -> Result<$ty, String> {
+                let n = f64::from_slot(slots, slot)?;
+                let min = (<$ty>::MIN as f64).max(-MAX_EXACT);
+                let max = (<$ty>::MAX as f64).min(MAX_EXACT);
+                if n.fract() != 0.0 || n < min || n > max {
+                    return Err(format!("{} is out of range for {}", n, stringify!($ty)));
+                }
+                Ok(n as $ty)
+            }
+        }
+
+        impl IntoSlot for $ty {
+            fn into_slot(self, slots: &mut Slots, slot: usize) -> Result<(), String> {
+                let n = self as f64;
+                if n.abs() > MAX_EXACT || n as $ty != self {
+                    return Err(format!("{} is out of range for a Wren number", self));
+                }
+                n.into_slot(slots, slot)
+            }
+        }
+    )*)
+}
+
+impl_int_slot!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+impl FromSlot for f32 {
+    fn from_slot(slots: &mut Slots, slot: usize) -> Result<f32, String> {
+        Ok(f64::from_slot(slots, slot)? as f32)
+    }
+}
+
+impl IntoSlot for f32 {
+    fn into_slot(self, slots: &mut Slots, slot: usize) -> Result<(), String> {
+        (self as f64).into_slot(slots, slot)
+    }
+}
+
+impl FromSlot for char {
+    fn from_slot(slots: &mut Slots, slot: usize) -> Result<char, String> {
+        let s = String::from_slot(slots, slot)?;
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Ok(c),
+            _ => Err(format!("expecting a single character for slot {}, got {:?}", slot, s)),
+        }
+    }
+}
+
+impl IntoSlot for char {
+    fn into_slot(self, slots: &mut Slots, slot: usize) -> Result<(), String> {
+        self.to_string().into_slot(slots, slot)
+    }
+}
+
+impl<'a> IntoSlot for &'a [u8] {
+    fn into_slot(self, slots: &mut Slots, slot: usize) -> Result<(), String> {
+        let slot = slots.check(slot)?;
+        unsafe {
+            wren_sys::wrenSetSlotBytes(slots.vm, slot, self.as_ptr() as *const c_char, self.len())
+        };
+        Ok(())
+    }
+}
+
+/// The contents of a Wren string as raw bytes, which need not be UTF-8.
+/// A `Vec<u8>` converts to and from a list of numbers instead.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Bytes(pub Vec<u8>);
+
+impl FromSlot for Bytes {
+    fn from_slot(slots: &mut Slots, slot: usize) -> Result<Bytes, String> {
+        slots.bytes(slot).map(Bytes)
+    }
+}
+
+impl IntoSlot for Bytes {
+    fn into_slot(self, slots: &mut Slots, slot: usize) -> Result<(), String> {
+        self.0.as_slice().into_slot(slots, slot)
+    }
+}
+
+/// Lists convert element by element.
+impl<T: FromSlot> FromSlot for Vec<T> {
+    fn from_slot(slots: &mut Slots, slot: usize) -> Result<Vec<T>, String> {
+        let list = slots.expect(slot, WrenType::WREN_TYPE_LIST, "Vec")?;
+        let count = unsafe { wren_sys::wrenGetListCount(slots.vm, list) };
+        slots.scratch(|slots, element| {
+            let mut values = Vec::with_capacity(count as usize);
+            for i in 0..count {
+                unsafe { wren_sys::wrenGetListElement(slots.vm, list, i, element as c_int) };
+                let value = T::from_slot(slots, element)
+                    .map_err(|e| format!("list element {}: {}", i, e))?;
+                values.push(value);
+            }
+            Ok(values)
+        })
+    }
+}
+
+impl<T: IntoSlot> IntoSlot for Vec<T> {
+    fn into_slot(self, slots: &mut Slots, slot: usize) -> Result<(), String> {
+        let list = slots.check(slot)?;
+        unsafe { wren_sys::wrenSetSlotNewList(slots.vm, list) };
+        slots.scratch(|slots, element| {
+            for (i, value) in self.into_iter().enumerate() {
+                value
+                    .into_slot(slots, element)
+                    .map_err(|e| format!("list element {}: {}", i, e))?;
+                unsafe { wren_sys::wrenInsertInList(slots.vm, list, -1, element as c_int) };
+            }
+            Ok(())
+        })
+    }
+}
+
+/// `null` converts to `None`.
+impl<T: FromSlot> FromSlot for Option<T> {
+    fn from_slot(slots: &mut Slots, slot: usize) -> Result<Option<T>, String> {
+        match slots.slot_type(slot)? {
+            WrenType::WREN_TYPE_NULL => Ok(None),
+            _ => T::from_slot(slots, slot).map(Some),
+        }
+    }
+}
+
+impl<T: IntoSlot> IntoSlot for Option<T> {
+    fn into_slot(self, slots: &mut Slots, slot: usize) -> Result<(), String> {
+        match self {
+            Some(value) => value.into_slot(slots, slot),
+            None => ().into_slot(slots, slot),
+        }
+    }
 }
 
 pub struct ForeignMethod {
@@ -30,23 +457,210 @@ pub struct ForeignMethod {
     pub method: unsafe extern "C" fn(*mut ::wren_sys::WrenVM),
 }
 
-pub trait WrenClass: Default + Sized {
-    const ID: usize;
+impl ForeignMethod {
+    /// Wraps a closure as a foreign method. `signature` is the parameter
+    /// part of the signature, such as `(_,_)`.
+    ///
+    /// An `Err` returned by the closure aborts the fiber with that message.
+    /// Wren only keeps a function pointer, so closures that capture anything
+    /// fail to build. `WrenBuilder::bind_closure` takes those.
+    pub fn new<F>(signature: &'static str, method: F) -> ForeignMethod
+    where
+        F: Fn(&mut Slots) -> Result<(), String> + Copy + 'static,
+    {
+        let () = Stateless::<F>::CHECK;
+        let _ = method;
+        ForeignMethod {
+            signature,
+            method: closure_trampoline::<F>,
+        }
+    }
+}
+
+// Evaluating `CHECK` fails the build for closures that capture anything.
+struct Stateless<F>(PhantomData<F>);
+
+impl<F> Stateless<F> {
+    const CHECK: () = assert!(
+        size_of::<F>() == 0,
+        "foreign method closures can't capture variables"
+    );
+}
+
+unsafe extern "C" fn closure_trampoline<F>(vm: *mut wren_sys::WrenVM)
+where
+    F: Fn(&mut Slots) -> Result<(), String> + Copy + 'static,
+{
+    with_slots(vm, |slots| {
+        // F is a zero-sized `Copy` type, so reading one out of thin air
+        // copies the closure that was given to `ForeignMethod::new`.
+        let method: F = ptr::read(ptr::NonNull::dangling().as_ptr());
+        if let Err(message) = method(slots) {
+            slots.abort(&message);
+        }
+    });
+}
+
+/// A Rust type that backs a Wren `foreign class`.
+///
+/// Usually derived with `#[derive(WrenClass)]`. Foreign objects are tagged
+/// with the `TypeId` of their Rust type, so distinct types can never be
+/// confused for each other.
+pub trait WrenClass: Sized + 'static {
     const MODULE: &'static str;
     const CLASS: &'static str;
+    /// Signatures of the constructors declared for the class when its
+    /// source is generated, e.g. `new(_)`.
+    const CONSTRUCTORS: &'static [&'static str] = &["new()"];
+}
+
+/// Builds the Rust value of a foreign object when a script calls one of its
+/// constructors. `args` holds the class in slot 0 followed by the arguments
+/// of the constructor.
+///
+/// Returning an error aborts the fiber with that message. Types that
+/// implement `DefaultConstruct` get an implementation that ignores the
+/// arguments.
+pub trait WrenConstruct: Sized {
+    fn construct(args: &mut Slots) -> Result<Self, String>;
+}
+
+/// Opts a `Default` type into being constructed with `Default::default()`,
+/// whatever the arguments. Usually implemented with `#[wren(default)]`.
+pub trait DefaultConstruct: Default {}
+
+impl<T: DefaultConstruct> WrenConstruct for T {
+    fn construct(_: &mut Slots) -> Result<Self, String> {
+        Ok(Default::default())
+    }
+}
+
+/// Binds every method of a foreign class at once, usually generated by
+/// `#[thrush::methods]` on the class's `impl` block.
+pub trait WrenMethods: WrenClass {
+    fn methods() -> Vec<BoundMethod>;
 }
 
-impl WrenClass for () {
-    const ID: usize = ::std::usize::MAX;
+pub struct BoundMethod {
+    pub is_static: bool,
+    pub name: &'static str,
+    pub method: ForeignMethod,
+}
+
+// Stand-in for objects of foreign classes that have no Rust binding, or
+// whose allocation failed.
+pub(crate) struct Invalid;
+
+impl WrenClass for Invalid {
     const MODULE: &'static str = "<none>";
-    const CLASS: &'static str = "Unit";
+    const CLASS: &'static str = "Invalid";
+}
+
+impl<T: WrenClass> IntoSlot for T {
+    fn into_slot(self, slots: &mut Slots, slot: usize) -> Result<(), String> {
+        let index = slots.check(slot)?;
+        let desc = ClassDesc {
+            module: from_str(T::MODULE),
+            class_name: from_str(T::CLASS),
+        };
+        let vm = slots.vm;
+        unsafe {
+            let user_data = wren_sys::wrenGetUserData(vm) as *const ::vm::UserData;
+            let foreigns = &(*user_data).foreigns;
+            if !foreigns.classes.contains_key(&desc) {
+                return Err("attempt to return unbound rust object".into());
+            }
+            // wrenGetVariable doesn't check that the variable exists, so the
+            // class has to have been declared by a script first.
+            if !foreigns.is_declared(&desc) {
+                return Err(format!(
+                    "attempt to return {} before its foreign class is declared",
+                    T::CLASS
+                ));
+            }
+
+            wren_sys::wrenGetVariable(vm, desc.module.as_ptr(), desc.class_name.as_ptr(), index);
+            let ptr = wren_sys::wrenSetSlotNewForeign(
+                vm,
+                index,
+                index,
+                size_of::<ForeignClass<T>>(),
+            ) as *mut ForeignClass<T>;
+            ptr::write(ptr, ForeignClass::new(self));
+        }
+        Ok(())
+    }
+}
+
+/// A shared borrow of a foreign object, from `Slots::borrow`.
+pub struct ForeignRef<'vm, T: WrenClass> {
+    object: *mut ForeignClass<T>,
+    _root: Root,
+    _vm: PhantomData<&'vm T>,
+}
+
+impl<'vm, T: WrenClass> Deref for ForeignRef<'vm, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { (*self.object).get_data() }
+    }
+}
+
+impl<'vm, T: WrenClass> Drop for ForeignRef<'vm, T> {
+    fn drop(&mut self) {
+        let borrow = unsafe { &(*self.object).borrow };
+        borrow.set(borrow.get() - 1);
+    }
+}
+
+/// A mutable borrow of a foreign object, from `Slots::borrow_mut`.
+pub struct ForeignRefMut<'vm, T: WrenClass> {
+    object: *mut ForeignClass<T>,
+    _root: Root,
+    _vm: PhantomData<&'vm mut T>,
+}
+
+impl<'vm, T: WrenClass> Deref for ForeignRefMut<'vm, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { (*self.object).get_data() }
+    }
+}
+
+impl<'vm, T: WrenClass> DerefMut for ForeignRefMut<'vm, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { (*self.object).get_data_mut() }
+    }
+}
+
+impl<'vm, T: WrenClass> Drop for ForeignRefMut<'vm, T> {
+    fn drop(&mut self) {
+        unsafe { (*self.object).borrow.set(0) };
+    }
+}
+
+// Keeps a borrowed object from being collected. Guards release it after
+// their own `drop` is done with the object.
+struct Root {
+    vm: *mut wren_sys::WrenVM,
+    handle: *mut wren_sys::WrenHandle,
+}
+
+impl Drop for Root {
+    fn drop(&mut self) {
+        unsafe { wren_sys::wrenReleaseHandle(self.vm, self.handle) };
+    }
 }
 
 #[doc(hidden)]
 #[repr(C)]
 pub struct ForeignClass<T: WrenClass> {
     valid: bool,
-    id: usize,
+    id: TypeId,
+    // Number of outstanding shared borrows, or -1 while mutably borrowed.
+    borrow: Cell<isize>,
     data: T,
 }
 
@@ -57,8 +671,9 @@ where
     pub fn new(data: T) -> Self {
         ForeignClass {
             valid: true,
+            id: TypeId::of::<T>(),
+            borrow: Cell::new(0),
             data,
-            id: T::ID,
         }
     }
 
@@ -70,7 +685,7 @@ where
         &mut self.data
     }
 
-    pub fn get_id(&self) -> usize {
+    pub fn get_id(&self) -> TypeId {
         self.id
     }
 
@@ -79,28 +694,76 @@ where
     }
 }
 
-impl<T> Default for ForeignClass<T>
-where
-    T: WrenClass,
-{
-    fn default() -> Self {
-        ForeignClass {
-            data: Default::default(),
-            id: T::ID,
-            valid: true,
-        }
+impl Foreign {
+    /// Whether a script has executed the `foreign class` declaration for
+    /// `desc`, meaning its class object can be looked up.
+    pub fn is_declared(&self, desc: &ClassDesc) -> bool {
+        self.declared.borrow().contains(desc)
     }
-}
 
-impl Foreign {
-    pub(crate) fn bind_class<T: WrenClass>(&mut self) {
-        self.classes.insert(
-            ClassDesc {
-                module: from_str(T::MODULE),
-                class_name: from_str(T::CLASS),
-            },
-            T::bind_foreign(),
-        );
+    pub(crate) fn bind_class<T: WrenClass + WrenConstruct>(&mut self) {
+        let desc = ClassDesc {
+            module: from_str(T::MODULE),
+            class_name: from_str(T::CLASS),
+        };
+        self.constructors.insert(desc.clone(), T::CONSTRUCTORS);
+        self.classes.insert(desc, T::bind_foreign());
+    }
+
+    /// Renders the Wren declarations of every class and method bound in
+    /// `module`, or `None` if nothing is bound there.
+    ///
+    /// Bound classes become `foreign class`es. Methods bound on other
+    /// classes are declared in a plain `class`.
+    pub fn render_module(&self, module: &str) -> Option<String> {
+        let module = from_str(module);
+        let mut names = self.classes
+            .keys()
+            .filter(|desc| desc.module == module)
+            .map(|desc| desc.class_name.clone())
+            .chain(
+                self.methods
+                    .keys()
+                    .filter(|desc| desc.module == module)
+                    .map(|desc| desc.class_name.clone()),
+            )
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        if names.is_empty() {
+            return None;
+        }
+
+        let mut source = String::new();
+        for class_name in names {
+            let desc = ClassDesc {
+                module: module.clone(),
+                class_name,
+            };
+            let name = desc.class_name.to_string_lossy();
+            if self.classes.contains_key(&desc) {
+                source.push_str(&format!("foreign class {} {{\n", name));
+                let constructors = self.constructors.get(&desc).cloned().unwrap_or(&[]);
+                for sig in constructors {
+                    source.push_str(&format!("  construct {} {{}}\n", declaration(sig)));
+                }
+            } else {
+                source.push_str(&format!("class {} {{\n", name));
+            }
+
+            let mut methods = self.methods
+                .keys()
+                .filter(|m| m.module == desc.module && m.class_name == desc.class_name)
+                .map(|m| (!m.is_static, m.signature.to_string_lossy().into_owned()))
+                .collect::<Vec<_>>();
+            methods.sort();
+            for (is_instance, sig) in methods {
+                let keyword = if is_instance { "foreign" } else { "foreign static" };
+                source.push_str(&format!("  {} {}\n", keyword, declaration(&sig)));
+            }
+            source.push_str("}\n\n");
+        }
+        Some(source)
     }
 
     pub(crate) fn bind_method(
@@ -121,6 +784,46 @@ impl Foreign {
             Some(method.method),
         );
     }
+
+    pub(crate) fn bind_closure<F>(
+        &mut self,
+        module: &str,
+        class_name: &str,
+        is_static: bool,
+        name: &str,
+        signature: &str,
+        method: F,
+    ) -> Result<(), WrenError>
+    where
+        F: Fn(&mut Slots) -> Result<(), String> + 'static,
+    {
+        let desc = MethodDesc {
+            module: from_str(module),
+            class_name: from_str(class_name),
+            is_static,
+            signature: from_str(&format!("{}{}", name, signature)),
+        };
+        let method = BoxedMethod(Box::new(method));
+        let index = match self.closure_index.get(&desc).cloned() {
+            Some(index) => {
+                self.closures[index] = method;
+                index
+            }
+            None => {
+                let index = self.closures.len();
+                if index == TRAMPOLINES.len() {
+                    return Err(WrenError::TooManyClosures {
+                        limit: TRAMPOLINES.len(),
+                    });
+                }
+                self.closures.push(method);
+                self.closure_index.insert(desc.clone(), index);
+                index
+            }
+        };
+        self.methods.insert(desc, Some(TRAMPOLINES[index]));
+        Ok(())
+    }
 }
 
 trait ToWren {
@@ -129,7 +832,7 @@ trait ToWren {
 
 impl<T> ToWren for T
 where
-    T: WrenClass,
+    T: WrenClass + WrenConstruct,
 {
     fn bind_foreign() -> wren_sys::WrenForeignClassMethods {
         wren_sys::WrenForeignClassMethods {
@@ -141,10 +844,32 @@ where
 
 pub(crate) unsafe extern "C" fn alloc_foreign_class<T>(vm: *mut wren_sys::WrenVM)
 where
-    T: WrenClass,
+    T: WrenClass + WrenConstruct,
 {
+    let _held = memory::hold();
     use std::mem::{forget, size_of, swap};
-    let mut v: ForeignClass<T> = Default::default();
+    // The constructor gets the arguments with the class in slot 0, which it
+    // is free to overwrite, so the class is put back afterwards.
+    let class = wren_sys::wrenGetSlotHandle(vm, 0);
+    let constructed = match check_memory(vm) {
+        Ok(()) => panic::catch_unwind(AssertUnwindSafe(|| T::construct(&mut Slots::new(vm)))),
+        Err(message) => Ok(Err(message)),
+    };
+    wren_sys::wrenSetSlotHandle(vm, 0, class);
+    wren_sys::wrenReleaseHandle(vm, class);
+    let mut v = match constructed {
+        Ok(Ok(data)) => ForeignClass::new(data),
+        Ok(Err(message)) => {
+            alloc_invalid_class(vm);
+            Slots::new(vm).abort(&message);
+            return;
+        }
+        Err(payload) => {
+            alloc_invalid_class(vm);
+            abort_with_panic(vm, payload);
+            return;
+        }
+    };
     wren_sys::wrenEnsureSlots(vm, 1);
     let p = wren_sys::wrenSetSlotNewForeign(vm, 0, 0, size_of::<ForeignClass<T>>()) as
         *mut ForeignClass<T>;
@@ -153,22 +878,95 @@ where
 }
 
 pub(crate) unsafe extern "C" fn alloc_invalid_class(vm: *mut wren_sys::WrenVM) {
+    let _held = memory::hold();
     use std::mem::{forget, size_of, swap};
-    let mut data = ForeignClass::<()>::default();
+    let mut data = ForeignClass::new(Invalid);
     data.valid = false;
     wren_sys::wrenEnsureSlots(vm, 1);
-    let p = wren_sys::wrenSetSlotNewForeign(vm, 0, 0, size_of::<ForeignClass<()>>()) as
-        *mut ForeignClass<()>;
+    let p = wren_sys::wrenSetSlotNewForeign(vm, 0, 0, size_of::<ForeignClass<Invalid>>()) as
+        *mut ForeignClass<Invalid>;
     swap(&mut data, &mut *p);
     forget(data);
 }
 
 pub(crate) unsafe extern "C" fn finalize_foreign_class<T: WrenClass>(ptr: *mut c_void) {
-    use std::mem::{swap, uninitialized};
-    let mut v: ForeignClass<T> = uninitialized();
-    swap(&mut v, &mut *(ptr as *mut ForeignClass<T>));
-    if v.id != T::ID {
-        panic!("invalid cast of foreign class in finalizer")
+    let _held = memory::hold();
+    let ptr = ptr as *mut ForeignClass<T>;
+    // Objects whose allocation failed only have the header in common with
+    // ForeignClass<T>, so there's nothing more to look at.
+    if !(*ptr).valid || (*ptr).id != TypeId::of::<T>() {
+        return;
+    }
+    // There is no fiber to report a panic to at this point, and it must not
+    // unwind into Wren.
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| ptr::drop_in_place(ptr)));
+}
+
+/// Turns a signature like `at(_,_)` into the declaration `at(arg0, arg1)`.
+fn declaration(signature: &str) -> String {
+    let mut out = String::with_capacity(signature.len());
+    let mut arg = 0;
+    let mut prev = ' ';
+    for c in signature.chars() {
+        let in_params = prev == '(' || prev == '[' || prev == ',';
+        prev = c;
+        match c {
+            '_' if in_params => {
+                out.push_str(&format!("arg{}", arg));
+                arg += 1;
+            }
+            ',' => out.push_str(", "),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Runs the body of a foreign method, turning a panic into a fiber abort
+/// instead of letting it unwind into Wren.
+#[doc(hidden)]
+pub unsafe fn guard<F: FnOnce()>(vm: *mut wren_sys::WrenVM, body: F) {
+    let _held = memory::hold();
+    if let Err(message) = check_memory(vm) {
+        Slots::new(vm).abort(&message);
+        return;
+    }
+    if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(body)) {
+        abort_with_panic(vm, payload);
+    }
+}
+
+/// Like `guard`, handing the body the slots of the call. They can't outlive
+/// the body, and neither can anything borrowed from them.
+#[doc(hidden)]
+pub unsafe fn with_slots<F>(vm: *mut wren_sys::WrenVM, body: F)
+where
+    F: for<'vm> FnOnce(&mut Slots<'vm>),
+{
+    guard(vm, || body(&mut Slots::new(vm)))
+}
+
+// Scripts that went past their memory limit are stopped the next time they
+// call into Rust.
+unsafe fn check_memory(vm: *mut wren_sys::WrenVM) -> Result<(), String> {
+    let user_data = wren_sys::wrenGetUserData(vm) as *const ::vm::UserData;
+    if (*user_data).memory.exceeded() {
+        Err("out of memory".into())
+    } else {
+        Ok(())
+    }
+}
+
+unsafe fn abort_with_panic(vm: *mut wren_sys::WrenVM, payload: Box<dyn Any + Send>) {
+    let message = format!("rust panic: {}", panic_message(&payload));
+    wren_sys::wrenEnsureSlots(vm, 1);
+    let mut slots = Slots::new(vm);
+    slots.abort(&message);
+    // Slot 0 holds the error string the fiber was aborted with.
+    let text = wren_sys::wrenGetSlotString(vm, 0);
+    if let Ok(handle) = slots.get(0) {
+        let user_data = wren_sys::wrenGetUserData(vm) as *mut ::vm::UserData;
+        (*user_data).errors.record_panic(text, handle);
     }
 }
 
@@ -178,6 +976,7 @@ pub(crate) unsafe extern "C" fn bind_foreign_class(
     module: *const c_char,
     className: *const c_char,
 ) -> wren_sys::WrenForeignClassMethods {
+    let _held = memory::hold();
     let user_data = wren_sys::wrenGetUserData(vm) as *const ::vm::UserData;
     let foreigns = &(*user_data).foreigns;
     let desc = ClassDesc {
@@ -185,10 +984,13 @@ pub(crate) unsafe extern "C" fn bind_foreign_class(
         class_name: c_string(className),
     };
     match foreigns.classes.get(&desc) {
-        Some(binding) => *binding,
+        Some(binding) => {
+            foreigns.declared.borrow_mut().insert(desc);
+            *binding
+        }
         None => wren_sys::WrenForeignClassMethods {
             allocate: Some(alloc_invalid_class),
-            finalize: Some(finalize_foreign_class::<()>),
+            finalize: Some(finalize_foreign_class::<Invalid>),
         },
     }
 }
@@ -201,6 +1003,7 @@ pub(crate) unsafe extern "C" fn bind_foreign_method(
     isStatic: bool,
     signature: *const c_char,
 ) -> wren_sys::WrenForeignMethodFn {
+    let _held = memory::hold();
     let user_data = wren_sys::wrenGetUserData(vm) as *const ::vm::UserData;
     let foreigns = &(*user_data).foreigns;
     let desc = MethodDesc {
@@ -1,7 +1,15 @@
+extern crate thrush_derive;
 extern crate wren_sys;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
 
 pub(crate) mod util;
 
+mod closures;
+
+mod memory;
+
 #[macro_use]
 mod macros;
 
@@ -9,8 +17,26 @@ pub mod vm;
 
 pub mod errors;
 
+pub mod report;
+
 pub mod foreign;
 
+pub mod module;
+
+pub mod output;
+
+mod value;
+
+pub use value::Value;
+
+#[cfg(feature = "serde")]
+mod serialization;
+
+#[cfg(feature = "serde")]
+pub use serialization::{from_wren, to_wren};
+
+pub use thrush_derive::methods;
+
 pub mod sys {
     pub use wren_sys::*;
 }
@@ -1,186 +1,166 @@
+/// Builds a `ForeignMethod` out of a parameter list and a body.
+///
+/// Parameters are converted with `FromSlot` and the return value with
+/// `IntoSlot`, so any type implementing those can be used. `[T]` mutably
+/// borrows the Rust value of a foreign object of class `T` and `&[T]` borrows
+/// it, see `Slots::borrow`. `&str` reads a `String` and passes a reference to
+/// it. `_` skips a slot.
+///
+/// The first argument names the `&mut Slots` of the call, which is also
+/// available in the body.
 #[macro_export]
 macro_rules! wren_fn {
-    ([[ $vm:expr ]] $block:block) => (
-        (|| $block)();
-        unsafe {
-            $crate::sys::wrenSetSlotNull($vm, 0);
+    ([[ $vm:expr ]] return $res:ident) => (
+        if let Err(e) = $vm.set(0, $res) {
+            wren_fn!([[ $vm ]] abort e);
         }
     );
-    ([[ $vm:expr ]] -> $ty:tt $block:block) => (
-        let res = (|| $block)();
-        wren_fn!([[ $vm ]] return res : $ty);
+    ([[ $vm:expr, $slot:ident ]] bind_var _) => ();
+    ([[ $vm:expr, $slot:ident ]] bind_var $var_name:ident [$ty:ty]) => (
+        #[allow(unused_mut)]
+        let mut $var_name = match $vm.borrow_mut::<$ty>($slot) {
+            Ok(object) => object,
+            Err(e) => wren_fn!([[ $vm ]] abort e),
+        };
     );
-    ([[ $vm:expr ]] return $res:tt : String) => (
-        let out = match ::std::ffi::CString::new($res) {
-            Ok(s) => s,
-            Err(e) => wren_fn!([[ $vm ]] abort format!("cstring convert error: {}", e)),
+    ([[ $vm:expr, $slot:ident ]] bind_var $var_name:ident &[$ty:ty]) => (
+        let $var_name = match $vm.borrow::<$ty>($slot) {
+            Ok(object) => object,
+            Err(e) => wren_fn!([[ $vm ]] abort e),
         };
-
-        unsafe {
-            $crate::sys::wrenSetSlotString($vm, 0, out.as_ptr());
-        }
     );
-    ([[ $vm:expr ]] return $res:ident : bool) => (
-        let out: bool = $res.into();
-        unsafe {
-            $crate::sys::wrenSetSlotBool($vm, 0, out);
-        }
+    ([[ $vm:expr, $slot:ident ]] bind_var $var_name:ident str) => (
+        let $var_name: String = match $vm.get($slot) {
+            Ok(value) => value,
+            Err(e) => wren_fn!([[ $vm ]] abort e),
+        };
+        let $var_name: &str = &$var_name;
     );
-    ([[ $vm:expr ]] return $res:ident : f64) => (
-        let out: f64 = $res.into();
-        unsafe {
-            $crate::sys::wrenSetSlotDouble($vm, 0, out);
-        }
+    ([[ $vm:expr, $slot:ident ]] bind_var $var_name:ident ($ty:ty)) => (
+        let $var_name: $ty = match $vm.get($slot) {
+            Ok(value) => value,
+            Err(e) => wren_fn!([[ $vm ]] abort e),
+        };
     );
-    ([[ $vm:expr ]] return $res:ident : _) => (
-    );
-    ([[ $vm:expr ]] return $res:ident : [$ty:ty]) => (
-        unsafe {
-            let res: $ty = $res.into();
-            let mut out: $crate::foreign::ForeignClass<$ty> = $crate::foreign::ForeignClass::new(res);
-
-            let module = ::std::ffi::CString::new(<$ty as $crate::foreign::WrenClass>::MODULE).unwrap();
-            let class_name = ::std::ffi::CString::new(<$ty as $crate::foreign::WrenClass>::CLASS).unwrap();
-
-            $crate::sys::wrenGetVariable($vm, module.as_ptr(), class_name.as_ptr(), 0);
-
-            let desc = $crate::foreign::ClassDesc {
-                module, class_name
-            };
-            let user_data = $crate::sys::wrenGetUserData($vm) as *const $crate::vm::UserData;
-            let foreigns = &(*user_data).foreigns;
-            if let None = foreigns.classes.get(&desc) {
-                wren_fn!([[ $vm ]] abort format!("attempt to return unbound rust object"));
-            }
-
-            let ptr: *mut $crate::foreign::ForeignClass<$ty> =
-                $crate::sys::wrenSetSlotNewForeign(
-                    $vm,
-                    0,
-                    0,
-                    ::std::mem::size_of::<$crate::foreign::ForeignClass<$ty>>(),
+    ([[ $vm:expr, $slot:ident ]] bind_vars $({ $($param:tt)* })*) => (
+        $(
+            wren_fn!([[ $vm, $slot ]] bind_var $($param)*);
+            $slot += 1;
+        )*
+    );
+    ([[ $vm:expr ]] params $($param:tt)*) => (
+        let total_slots = $vm.count();
+        let num_vars = wren_fn!(count_vars $($param)*);
+        if num_vars > (total_slots) {
+            wren_fn!([[ $vm ]] abort
+                format!(
+                    "invalid number of arguments. expecting {}, got {}.",
+                    num_vars,
+                    total_slots-1,
                 )
-                as *mut _;
-            ::std::mem::swap(&mut *ptr, &mut out);
-            ::std::mem::forget(out);
+            );
         }
+        #[allow(unused_variables)]
+        let mut current_slot: usize = 0;
+        wren_fn!([[ $vm, current_slot ]] bind_vars $($param)*);
+        drop(current_slot);
     );
-    ([[ $vm:expr, $slot:ident ]] bind_var $var_name:ident : f64) => (
-        let $var_name: f64 = unsafe { $crate::sys::wrenGetSlotNum($vm, $slot) };
-    );
-    ([[ $vm:expr, $slot:ident ]] bind_var $var_name:ident : bool) => (
-        let $var_name: bool = unsafe { $crate::sys::wrenGetSlotBool($vm, $slot) };
+    // The arguments go out of scope before the result is stored in slot 0,
+    // which may hold the only reference to the receiver.
+    ([[ $vm:expr ]] ($($t:tt)*) $block:block) => (
+        {
+            wren_fn!(normalize ([[ $vm ]] params) [] $($t)*);
+            (|| $block)();
+        }
+        let _ = $vm.set(0, ());
     );
-    ([[ $vm:expr, $slot:ident ]] bind_var $var_name:ident : String) => (
-        let $var_name: String = unsafe {
-            let ptr = $crate::sys::wrenGetSlotString($vm, $slot);
-            ::std::ffi::CStr::from_ptr(ptr).to_string_lossy().into()
+    ([[ $vm:expr ]] ($($t:tt)*) -> _ $block:block) => ({
+        wren_fn!(normalize ([[ $vm ]] params) [] $($t)*);
+        (|| $block)();
+    });
+    ([[ $vm:expr ]] ($($t:tt)*) -> [$ty:ty] $block:block) => (
+        let res: $ty = {
+            wren_fn!(normalize ([[ $vm ]] params) [] $($t)*);
+            (|| $block)()
         };
+        wren_fn!([[ $vm ]] return res);
     );
-    ([[ $vm:expr, $slot:ident ]] bind_var $var_name:ident : [$ty:ty]) => (
-        let $var_name: *mut $ty = unsafe {
-            let ptr = $crate::sys::wrenGetSlotForeign($vm, $slot) as *mut $crate::foreign::ForeignClass<$ty>;
-            if ! $crate::foreign::ForeignClass::<$ty>::is_valid(ptr) {
-                wren_fn!([[ $vm ]] abort "attempt to use unbound foreign class");
-            }
-            if (*ptr).get_id() != <$ty as $crate::foreign::WrenClass>::ID {
-                wren_fn!([[ $vm ]] abort format!("foreign object with invalid type id for {}", stringify!($ty)));
-            }
-            (*ptr).get_data_mut()
+    ([[ $vm:expr ]] ($($t:tt)*) -> $ty:ty $block:block) => (
+        let res: $ty = {
+            wren_fn!(normalize ([[ $vm ]] params) [] $($t)*);
+            (|| $block)()
         };
+        wren_fn!([[ $vm ]] return res);
     );
-    ([[ $vm:expr, $slot:ident ]] ensure_slot bool) => (
-        wren_fn!([[ $vm, $slot ]] ensure_slot bool WREN_TYPE_BOOL);
+    // Turns the parameter list into one `{ ... }` group per parameter so
+    // that the other rules don't have to parse types again.
+    (normalize ($($prefix:tt)*) [$($done:tt)*]) => (
+        wren_fn!($($prefix)* $($done)*)
     );
-    ([[ $vm:expr, $slot:ident ]] ensure_slot String) => (
-        wren_fn!([[ $vm, $slot ]] ensure_slot String WREN_TYPE_STRING);
+    (normalize $prefix:tt [$($done:tt)*] _ , $($rest:tt)*) => (
+        wren_fn!(normalize $prefix [$($done)* { _ }] $($rest)*)
     );
-    ([[ $vm:expr, $slot:ident ]] ensure_slot f64) => (
-        wren_fn!([[ $vm, $slot ]] ensure_slot f64 WREN_TYPE_NUM);
+    (normalize $prefix:tt [$($done:tt)*] _) => (
+        wren_fn!(normalize $prefix [$($done)* { _ }])
     );
-    ([[ $vm:expr, $slot:ident ]] ensure_slot $t:tt) => (
-        wren_fn!([[ $vm, $slot ]] ensure_slot $t WREN_TYPE_FOREIGN);
+    (normalize $prefix:tt [$($done:tt)*] $name:ident : [$ty:ty] , $($rest:tt)*) => (
+        wren_fn!(normalize $prefix [$($done)* { $name [$ty] }] $($rest)*)
     );
-    ([[ $vm:expr, $slot:ident ]] ensure_slot $rust:tt $wren:tt) => (
-        unsafe {
-            let slot_type = $crate::sys::wrenGetSlotType($vm, $slot);
-            use $crate::sys::WrenType::*;
-            match slot_type {
-                $wren => {}
-                t => wren_fn!([[ $vm ]] abort format!("expecting {} for variable {}, got {:?}.", stringify!($rust), $slot, t)),
-            }
-        }
+    (normalize $prefix:tt [$($done:tt)*] $name:ident : [$ty:ty]) => (
+        wren_fn!(normalize $prefix [$($done)* { $name [$ty] }])
     );
-    ([[ $vm:expr, $slot:ident ]] bind_vars ) => ();
-    ([[ $vm:expr, $slot:ident ]] bind_vars _ $(, $($t:tt):+)*) => (
-        $slot += 1;
-        wren_fn!([[ $vm, $slot ]] bind_vars $($($t):+),*);
+    (normalize $prefix:tt [$($done:tt)*] $name:ident : &[$ty:ty] , $($rest:tt)*) => (
+        wren_fn!(normalize $prefix [$($done)* { $name &[$ty] }] $($rest)*)
     );
-    ([[ $vm:expr, $slot:ident ]] bind_vars $var_name:ident : $var_type:tt $(,$($t:tt):+)*) => (
-        wren_fn!([[ $vm, $slot ]] ensure_slot $var_type);
-        wren_fn!([[ $vm, $slot ]] bind_var $var_name: $var_type);
-        $slot += 1;
-        wren_fn!([[ $vm, $slot ]] bind_vars $($($t):+),*);
+    (normalize $prefix:tt [$($done:tt)*] $name:ident : &[$ty:ty]) => (
+        wren_fn!(normalize $prefix [$($done)* { $name &[$ty] }])
     );
-    ([[ $vm:expr ]] ($($t:tt)*) $($rest:tt)*) => (
-        let total_slots = unsafe { $crate::sys::wrenGetSlotCount($vm) };
-        let num_vars = wren_fn!(count_vars $($t)*);
-        if num_vars > (total_slots) {
-            wren_fn!([[ $vm ]] abort 
-                format!(
-                    "invalid number of arguments. expecting {}, got {}.",
-                    num_vars,
-                    total_slots-1,
-                )
-            );
-        }
-        #[allow(unused_variables)]
-        let mut current_slot = 0;
-        wren_fn!([[ $vm, current_slot ]] bind_vars $($t)*);
-        drop(current_slot);
-        wren_fn!([[ $vm ]] $($rest)*);
+    (normalize $prefix:tt [$($done:tt)*] $name:ident : &str , $($rest:tt)*) => (
+        wren_fn!(normalize $prefix [$($done)* { $name str }] $($rest)*)
     );
-    (build_sig_args_rest) => ("");
-    (build_sig_args_rest $($head:tt):+) => (
-        "_"
+    (normalize $prefix:tt [$($done:tt)*] $name:ident : &str) => (
+        wren_fn!(normalize $prefix [$($done)* { $name str }])
     );
-    (build_sig_args_rest $($head:tt):+ $(, $($tail:tt):+)+) => (
-        concat!("_,", wren_fn!(build_sig_args_rest $($($tail):+),+))
+    (normalize $prefix:tt [$($done:tt)*] $name:ident : $ty:ty , $($rest:tt)*) => (
+        wren_fn!(normalize $prefix [$($done)* { $name ($ty) }] $($rest)*)
     );
-    (build_sig_args $($head:tt):+ ) => ("");
-    (build_sig_args $($head:tt):+ $(, $($tail:tt):+)+) => (
-        wren_fn!(build_sig_args_rest $($($tail):+),+)
+    (normalize $prefix:tt [$($done:tt)*] $name:ident : $ty:ty) => (
+        wren_fn!(normalize $prefix [$($done)* { $name ($ty) }])
     );
-    (build_sig Parens ($($t:tt)*) $($rest:tt)*) => (
-        concat!('(', wren_fn!(build_sig_args $($t)*), ')')
+    (build_sig_args) => ("");
+    (build_sig_args $receiver:tt) => ("");
+    (build_sig_args $receiver:tt $head:tt $($tail:tt)*) => (
+        concat!("_" $(, ",", wren_fn!(sig_arg $tail))*)
     );
-    (build_sig Brackets ($($t:tt)*) $($rest:tt)*) => (
-        concat!('[', wren_fn!(build_sig_args $($t)*), ']')
+    (sig_arg $param:tt) => ("_");
+    (build_sig Parens $($params:tt)*) => (
+        concat!('(', wren_fn!(build_sig_args $($params)*), ')')
     );
-    (build_sig None ($($t:tt)*) $($rest:tt)*) => (
-        wren_fn!(build_sig_args $($t)*)
+    (build_sig Brackets $($params:tt)*) => (
+        concat!('[', wren_fn!(build_sig_args $($params)*), ']')
     );
-    (count_vars) => (
-        0
+    (build_sig None $($params:tt)*) => (
+        wren_fn!(build_sig_args $($params)*)
     );
-    (count_vars $($head:tt):+ $(, $($tail:tt):+)* ) => (
-        1 + wren_fn!(count_vars $($($tail):+),*)
+    (count_vars $($param:tt)*) => (
+        0 $(+ wren_fn!(count_var $param))*
     );
+    (count_var $param:tt) => (1);
     ([[ $vm:expr ]] abort $msg:expr) => ({
-        let error_message = ::std::ffi::CString::new($msg).unwrap();
-        #[allow(unused_unsafe)]
-        unsafe {
-            $crate::sys::wrenSetSlotString($vm, 0, error_message.as_ptr());
-            $crate::sys::wrenAbortFiber($vm, 0);
-        }
+        $vm.abort(&$msg);
         return;
     });
-    ($vm_name:ident, $sig_type:tt, $($t:tt)+) => (
+    ($vm_name:ident, $sig_type:tt, ($($params:tt)*) $($t:tt)*) => (
         ForeignMethod {
-            signature: wren_fn!(build_sig $sig_type $($t)+),
+            signature: wren_fn!(normalize (build_sig $sig_type) [] $($params)*),
             method: {
-                extern fn ignoreme($vm_name: *mut $crate::sys::WrenVM) {
-                    wren_fn!([[ $vm_name ]] $($t)*);
+                unsafe extern "C" fn ignoreme(vm: *mut $crate::sys::WrenVM) {
+                    let body = |slots: &mut $crate::foreign::Slots| {
+                        #[allow(unused_variables)]
+                        let $vm_name = slots;
+                        wren_fn!([[ $vm_name ]] ($($params)*) $($t)*);
+                    };
+                    unsafe { $crate::foreign::with_slots(vm, body) };
                 }
                 ignoreme
             }
@@ -189,4 +169,4 @@ macro_rules! wren_fn {
     ($vm_name:ident, $($t:tt)+) => (
         wren_fn!($vm_name, Parens, $($t)+);
     );
-}
\ No newline at end of file
+}
@@ -0,0 +1,266 @@
+use std::cell::Cell;
+use std::mem;
+use std::os::raw::{c_int, c_void};
+use std::ptr;
+
+use wren_sys::{self, WrenVM};
+
+extern "C" {
+    fn malloc(size: usize) -> *mut c_void;
+    fn realloc(ptr: *mut c_void, size: usize) -> *mut c_void;
+    fn free(ptr: *mut c_void);
+}
+
+/// A snapshot of a VM's memory use, from `Wren::memory_stats`.
+///
+/// Everything Wren allocates is counted, including the VM itself and memory
+/// that is garbage but hasn't been collected yet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryStats {
+    /// Bytes currently allocated.
+    pub current_bytes: usize,
+    /// The most bytes allocated at any one time.
+    pub peak_bytes: usize,
+    /// Blocks allocated so far.
+    pub allocations: usize,
+    /// Blocks resized so far.
+    pub reallocations: usize,
+    /// Blocks freed so far.
+    pub frees: usize,
+}
+
+/// Allocation accounting for a single VM.
+#[derive(Debug)]
+pub(crate) struct Memory {
+    limit: Option<usize>,
+    stats: Cell<MemoryStats>,
+    exceeded: Cell<bool>,
+    // The VM, so that the allocator can collect garbage before giving up.
+    vm: Cell<*mut WrenVM>,
+    // Whether the allocator may escape out of the VM, see `protect`.
+    escapable: Cell<bool>,
+    poisoned: Cell<bool>,
+}
+
+impl Memory {
+    pub(crate) fn new(limit: Option<usize>) -> Memory {
+        Memory {
+            limit,
+            stats: Default::default(),
+            exceeded: Cell::new(false),
+            vm: Cell::new(ptr::null_mut()),
+            escapable: Cell::new(false),
+            poisoned: Cell::new(false),
+        }
+    }
+
+    pub(crate) fn attach(&self, vm: *mut WrenVM) {
+        self.vm.set(vm);
+    }
+
+    pub(crate) fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    pub(crate) fn stats(&self) -> MemoryStats {
+        self.stats.get()
+    }
+
+    /// Whether the VM was stopped in the middle of an allocation, which it
+    /// can't be resumed from.
+    pub(crate) fn poisoned(&self) -> bool {
+        self.poisoned.get()
+    }
+
+    /// Whether the VM went past its limit since the last `begin`.
+    pub(crate) fn exceeded(&self) -> bool {
+        self.exceeded.get()
+    }
+
+    /// Starts accounting for a new interpret or call. A VM still holding more
+    /// than its limit stays out of memory.
+    pub(crate) fn begin(&self) {
+        self.exceeded.set(self.over_limit());
+    }
+
+    // Blocks are never empty, so a zero `old` is a new block and a zero `new`
+    // a freed one.
+    fn resize(&self, old: usize, new: usize) {
+        let mut stats = self.stats.get();
+        stats.current_bytes = stats.current_bytes - old + new;
+        stats.peak_bytes = stats.peak_bytes.max(stats.current_bytes);
+        match (old, new) {
+            (0, _) => stats.allocations += 1,
+            (_, 0) => stats.frees += 1,
+            _ => stats.reallocations += 1,
+        }
+        self.stats.set(stats);
+        if new > old && self.over_limit() {
+            self.exceeded.set(true);
+        }
+    }
+
+    pub(crate) fn over_limit(&self) -> bool {
+        !self.fits(0, 0)
+    }
+
+    // Whether a block growing from `old` to `new` bytes keeps within the limit.
+    fn fits(&self, old: usize, new: usize) -> bool {
+        match self.limit {
+            Some(limit) => self.stats.get().current_bytes - old + new <= limit,
+            None => true,
+        }
+    }
+
+    /// Runs `body`, a call into Wren through one of the protected calls of
+    /// `wren_sys`, letting the allocator escape out of it rather than go
+    /// past the limit. Returns what the protected call did.
+    ///
+    /// Only Wren's own frames may lie between the protected call and the
+    /// allocator, so every callback into Rust holds off escapes with `hold`.
+    pub(crate) fn protect<F: FnOnce() -> c_int>(&self, body: F) -> c_int {
+        let outer = self.escapable.replace(self.limit.is_some());
+        let finished = body();
+        self.escapable.set(outer);
+        finished
+    }
+
+    // Called before a block grows from `old` to `new` bytes. Past the limit,
+    // the allocation is refused by escaping out of the VM, so this frame
+    // must not own anything that needs dropping.
+    //
+    // New blocks collect garbage first, which Wren may do before any of its
+    // allocations anyway. Growing blocks can't, since Wren grows its gray
+    // stack in the middle of a collection.
+    unsafe fn reserve(&self, old: usize, new: usize) {
+        if !self.escapable.get() || self.fits(old, new) {
+            return;
+        }
+        if old == 0 {
+            self.escapable.set(false);
+            wren_sys::wrenCollectGarbage(self.vm.get());
+            self.escapable.set(true);
+        }
+        if !self.fits(old, new) {
+            self.escapable.set(false);
+            self.exceeded.set(true);
+            self.poisoned.set(true);
+            wren_sys::wrensys_escape();
+        }
+    }
+}
+
+thread_local! {
+    static CURRENT: Cell<*const Memory> = const { Cell::new(ptr::null()) };
+}
+
+/// Makes `memory` the account new allocations are charged to until the
+/// returned guard is dropped.
+///
+/// Wren's allocator doesn't say which VM an allocation is for, so this has
+/// to be set around everything that calls into a VM.
+pub(crate) fn enter(memory: &Memory) -> Entered {
+    let previous = CURRENT.with(|current| current.replace(memory));
+    Entered { previous }
+}
+
+pub(crate) struct Entered {
+    previous: *const Memory,
+}
+
+impl Drop for Entered {
+    fn drop(&mut self) {
+        CURRENT.with(|current| current.set(self.previous));
+    }
+}
+
+/// Keeps the allocator of the current VM from escaping until the returned
+/// guard is dropped. Every callback from Wren into Rust takes one, since
+/// escaping would skip its frames.
+pub(crate) fn hold() -> Held {
+    let memory = CURRENT.with(|current| current.get());
+    let escapable = !memory.is_null() && unsafe { (*memory).escapable.replace(false) };
+    Held { memory, escapable }
+}
+
+pub(crate) struct Held {
+    memory: *const Memory,
+    escapable: bool,
+}
+
+impl Drop for Held {
+    fn drop(&mut self) {
+        if !self.memory.is_null() {
+            unsafe { (*self.memory).escapable.set(self.escapable) };
+        }
+    }
+}
+
+// Every block starts with a header recording its size and the account it was
+// charged to, so that it can be released against the same account no matter
+// which VM is current. 16 bytes keeps the block itself aligned like malloc's.
+#[repr(C)]
+struct Header {
+    memory: *const Memory,
+    size: usize,
+}
+
+const HEADER: usize = 16;
+
+unsafe fn header(block: *mut c_void) -> *mut Header {
+    (block as *mut u8).sub(HEADER) as *mut Header
+}
+
+/// The `reallocateFn` given to every VM.
+///
+/// Wren doesn't check for failed allocations, so a script that needs memory
+/// past the limit is stopped by escaping out of the VM instead. Allocations
+/// made while Rust code runs can't escape, so blocks past the limit are
+/// still handed out to them. The overrun is recorded instead, and enforced
+/// the next time the VM calls into Rust or returns to the host.
+pub(crate) unsafe extern "C" fn reallocate(block: *mut c_void, size: usize) -> *mut c_void {
+    debug_assert!(mem::size_of::<Header>() <= HEADER);
+    if block.is_null() {
+        return if size == 0 { ptr::null_mut() } else { allocate(size) };
+    }
+
+    let old = header(block);
+    let Header { memory, size: old_size } = ptr::read(old);
+    if size == 0 {
+        free(old as *mut c_void);
+        if !memory.is_null() {
+            (*memory).resize(old_size, 0);
+        }
+        return ptr::null_mut();
+    }
+
+    if !memory.is_null() && size > old_size {
+        (*memory).reserve(old_size, size);
+    }
+    let new = realloc(old as *mut c_void, HEADER + size) as *mut Header;
+    if new.is_null() {
+        return ptr::null_mut();
+    }
+    (*new).size = size;
+    if !memory.is_null() {
+        (*memory).resize(old_size, size);
+    }
+    (new as *mut u8).add(HEADER) as *mut c_void
+}
+
+/// Allocates a block that Wren can release, charged to the current VM.
+pub(crate) unsafe fn allocate(size: usize) -> *mut c_void {
+    let memory = CURRENT.with(|current| current.get());
+    if !memory.is_null() {
+        (*memory).reserve(0, size);
+    }
+    let new = malloc(HEADER + size) as *mut Header;
+    if new.is_null() {
+        return ptr::null_mut();
+    }
+    ptr::write(new, Header { memory, size });
+    if !memory.is_null() {
+        (*memory).resize(0, size);
+    }
+    (new as *mut u8).add(HEADER) as *mut c_void
+}
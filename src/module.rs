@@ -0,0 +1,196 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+/// Supplies the source for modules pulled in with `import`.
+///
+/// Any `FnMut(&str) -> Option<String>` is a loader, so a closure can be
+/// handed straight to `WrenBuilder::module_loader`.
+pub trait ModuleLoader {
+    fn load(&mut self, name: &str) -> Option<String>;
+
+    /// Where the source for `name` came from, for error reports.
+    fn locate(&mut self, _name: &str) -> Option<PathBuf> {
+        None
+    }
+}
+
+impl<F> ModuleLoader for F
+where
+    F: FnMut(&str) -> Option<String>,
+{
+    fn load(&mut self, name: &str) -> Option<String> {
+        self(name)
+    }
+}
+
+/// Loads `import "foo/bar"` from `<root>/foo/bar.wren`, trying each search
+/// root in the order they were added.
+#[derive(Debug, Clone)]
+pub struct FileLoader {
+    roots: Vec<PathBuf>,
+    extension: String,
+}
+
+impl Default for FileLoader {
+    fn default() -> Self {
+        FileLoader {
+            roots: vec![],
+            extension: "wren".into(),
+        }
+    }
+}
+
+impl FileLoader {
+    pub fn new() -> FileLoader {
+        Default::default()
+    }
+
+    pub fn root<P: Into<PathBuf>>(mut self, root: P) -> Self {
+        self.roots.push(root.into());
+        self
+    }
+
+    pub fn extension<S: Into<String>>(mut self, extension: S) -> Self {
+        self.extension = extension.into();
+        self
+    }
+
+    pub fn roots(&self) -> &[PathBuf] {
+        &self.roots
+    }
+
+    /// Finds the file backing the module `name`, if any.
+    ///
+    /// Names that are absolute or contain `..` are rejected so that scripts
+    /// can't reach outside of the search roots.
+    pub fn resolve(&self, name: &str) -> Option<PathBuf> {
+        let relative = Path::new(name);
+        let contained = relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)));
+        if name.is_empty() || !contained {
+            return None;
+        }
+        self.roots
+            .iter()
+            .map(|root| root.join(format!("{}.{}", name, self.extension)))
+            .find(|path| path.is_file())
+    }
+}
+
+impl ModuleLoader for FileLoader {
+    fn load(&mut self, name: &str) -> Option<String> {
+        let path = self.resolve(name)?;
+        let mut source = String::new();
+        File::open(path)
+            .and_then(|mut f| f.read_to_string(&mut source))
+            .ok()?;
+        Some(source)
+    }
+
+    fn locate(&mut self, name: &str) -> Option<PathBuf> {
+        self.resolve(name)
+    }
+}
+
+/// The source of a module loaded into a VM.
+#[derive(Debug, Clone)]
+pub struct ModuleSource {
+    name: String,
+    path: Option<PathBuf>,
+    source: String,
+    // Lines of generated foreign declarations compiled ahead of `source`.
+    generated_lines: u32,
+    // How many sources were compiled into the module, see `ModuleRegistry`.
+    chunks: u32,
+}
+
+impl ModuleSource {
+    pub(crate) fn new(
+        name: String,
+        path: Option<PathBuf>,
+        source: String,
+        generated: Option<&str>,
+    ) -> ModuleSource {
+        ModuleSource {
+            name,
+            path,
+            source,
+            generated_lines: generated.map_or(0, |g| g.matches('\n').count() as u32),
+            chunks: 1,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The file the module was loaded from, if its loader knows.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// The source as written, without any generated declarations.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Whether a line number reported by Wren is in the generated foreign
+    /// declarations compiled ahead of `source`.
+    pub fn is_generated(&self, line: u32) -> bool {
+        line >= 1 && line <= self.generated_lines
+    }
+
+    /// Maps a line number reported by Wren to the line number within
+    /// `source` and its text. Lines in generated declarations have no
+    /// counterpart, and neither do any once `main` has been interpreted
+    /// more than once.
+    pub fn line(&self, line: u32) -> Option<(u32, &str)> {
+        if self.chunks > 1 {
+            return None;
+        }
+        let line = line.checked_sub(self.generated_lines)?;
+        let text = self.source.lines().nth(line.checked_sub(1)? as usize)?;
+        Some((line, text))
+    }
+}
+
+/// Every module resident in a VM, in the order they were loaded.
+///
+/// `main` holds the source of the latest `Wren::interpret`. Wren numbers the
+/// lines of each one from 1, so a line reported in `main` after the first
+/// can't be told apart from the same line of an earlier one, and isn't
+/// mapped back to source.
+#[derive(Debug, Default)]
+pub struct ModuleRegistry {
+    modules: Vec<ModuleSource>,
+}
+
+impl ModuleRegistry {
+    pub fn get(&self, name: &str) -> Option<&ModuleSource> {
+        self.modules.iter().find(|module| module.name == name)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.modules.iter().map(|module| module.name()).collect()
+    }
+
+    pub fn iter(&self) -> ::std::slice::Iter<'_, ModuleSource> {
+        self.modules.iter()
+    }
+
+    pub(crate) fn insert(&mut self, mut module: ModuleSource) {
+        match self.modules.iter().position(|m| m.name == module.name) {
+            Some(index) => {
+                module.chunks += self.modules[index].chunks;
+                self.modules[index] = module;
+            }
+            None => self.modules.push(module),
+        }
+    }
+}
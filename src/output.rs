@@ -0,0 +1,38 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Where a VM's output goes, see `WrenBuilder::write_fn`.
+pub type WriteFn = dyn FnMut(&str);
+
+/// A shared buffer that collects everything a VM writes with `System.print`
+/// and friends. Clones share the same buffer.
+#[derive(Debug, Clone, Default)]
+pub struct CapturedOutput {
+    buffer: Rc<RefCell<String>>,
+}
+
+impl CapturedOutput {
+    pub fn new() -> CapturedOutput {
+        Default::default()
+    }
+
+    /// A write function appending to this buffer, for `WrenBuilder::write_fn`.
+    pub fn writer(&self) -> Box<WriteFn> {
+        let buffer = self.buffer.clone();
+        Box::new(move |text: &str| buffer.borrow_mut().push_str(text))
+    }
+
+    pub fn contents(&self) -> String {
+        self.buffer.borrow().clone()
+    }
+
+    /// Returns everything written so far and empties the buffer.
+    pub fn take(&self) -> String {
+        let mut buffer = self.buffer.borrow_mut();
+        ::std::mem::take(&mut *buffer)
+    }
+
+    pub fn clear(&self) {
+        self.buffer.borrow_mut().clear();
+    }
+}
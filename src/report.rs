@@ -0,0 +1,270 @@
+use std::fmt::Write;
+
+use errors::*;
+use module::ModuleRegistry;
+
+/// Renders a `WrenError` for people or tools, quoting the source of the
+/// modules involved.
+///
+/// Locations use the file a module was loaded from when its loader knows
+/// it, and the module name otherwise. Lines in the foreign declarations
+/// generated for a module are labelled as such.
+pub struct Report<'a> {
+    error: &'a WrenError,
+    modules: &'a ModuleRegistry,
+}
+
+struct Location<'a> {
+    name: String,
+    line: u32,
+    text: Option<&'a str>,
+    generated: bool,
+}
+
+#[derive(Clone, Copy)]
+struct Paint(bool);
+
+impl Paint {
+    fn paint(self, code: &str, text: &str) -> String {
+        if self.0 {
+            format!("\x1b[{}m{}\x1b[0m", code, text)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+impl<'a> Report<'a> {
+    pub fn new(error: &'a WrenError, modules: &'a ModuleRegistry) -> Report<'a> {
+        Report { error, modules }
+    }
+
+    pub fn plain(&self) -> String {
+        self.render(Paint(false))
+    }
+
+    /// Like `plain`, coloured with ANSI escape codes for terminals.
+    pub fn ansi(&self) -> String {
+        self.render(Paint(true))
+    }
+
+    /// A single JSON object with the error's `kind` and `message`, plus the
+    /// located `diagnostics` of compile errors or the `stack` of runtime
+    /// errors.
+    pub fn json(&self) -> String {
+        let mut out = String::from("{");
+        let kind = match *self.error {
+            WrenError::Compile { .. } => "compile",
+            WrenError::Runtime { .. } => "runtime",
+            WrenError::Arity { .. } => "arity",
+            WrenError::Conversion(_) => "conversion",
+            WrenError::InvalidHandle => "invalid_handle",
+            WrenError::UnknownVariable { .. } => "unknown_variable",
+            WrenError::OutOfMemory { .. } => "out_of_memory",
+            WrenError::DuplicateModule(_) => "duplicate_module",
+            WrenError::InvalidSignature(_) => "invalid_signature",
+            WrenError::TooManyClosures { .. } => "too_many_closures",
+        };
+        let _ = write!(
+            out,
+            "\"kind\":{},\"message\":{}",
+            json_string(kind),
+            json_string(&self.error.to_string())
+        );
+        match *self.error {
+            WrenError::Compile { ref errors, .. } => {
+                out.push_str(",\"diagnostics\":[");
+                for (i, diagnostic) in errors.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    let location = self.locate(&diagnostic.module, diagnostic.line);
+                    let _ = write!(
+                        out,
+                        "{{\"message\":{},{}}}",
+                        json_string(&diagnostic.message),
+                        location.json(&diagnostic.module)
+                    );
+                }
+                out.push(']');
+            }
+            WrenError::Runtime {
+                ref stack,
+                panicked,
+                ..
+            } => {
+                let _ = write!(out, ",\"panicked\":{},\"stack\":[", panicked);
+                for (i, trace) in stack.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    let location = self.locate(&trace.module, trace.line);
+                    let _ = write!(
+                        out,
+                        "{{\"function\":{},{}}}",
+                        json_string(&trace.function),
+                        location.json(&trace.module)
+                    );
+                }
+                out.push(']');
+            }
+            _ => {}
+        }
+        out.push('}');
+        out
+    }
+
+    fn render(&self, paint: Paint) -> String {
+        let mut out = String::new();
+        match *self.error {
+            WrenError::Compile { ref errors, .. } => {
+                let locations = errors
+                    .iter()
+                    .map(|diagnostic| self.locate(&diagnostic.module, diagnostic.line))
+                    .collect::<Vec<_>>();
+                let width = gutter_width(&locations);
+                for (i, (diagnostic, location)) in errors.iter().zip(&locations).enumerate() {
+                    if i > 0 {
+                        out.push('\n');
+                    }
+                    header(&mut out, &diagnostic.message, paint);
+                    location.snippet(&mut out, None, width, paint);
+                }
+            }
+            WrenError::Runtime {
+                ref message,
+                ref stack,
+                panicked,
+                ..
+            } => {
+                header(&mut out, message, paint);
+                let locations = stack
+                    .iter()
+                    .map(|trace| self.locate(&trace.module, trace.line))
+                    .collect::<Vec<_>>();
+                let width = gutter_width(&locations);
+                for (trace, location) in stack.iter().zip(&locations) {
+                    location.snippet(&mut out, Some(&trace.function), width, paint);
+                }
+                if panicked {
+                    let _ = writeln!(
+                        out,
+                        "{} {} note: raised by a panic in a foreign method",
+                        " ".repeat(width),
+                        paint.paint("1;34", "=")
+                    );
+                }
+            }
+            ref error => header(&mut out, &error.to_string(), paint),
+        }
+        out
+    }
+
+    fn locate(&self, module: &str, line: u32) -> Location<'a> {
+        let source = match self.modules.get(module) {
+            Some(source) => source,
+            None => {
+                return Location {
+                    name: module.to_string(),
+                    line,
+                    text: None,
+                    generated: false,
+                }
+            }
+        };
+        // Generated declarations aren't in the module's file, so they are
+        // placed by module name and Wren's own line number.
+        if source.is_generated(line) {
+            return Location {
+                name: module.to_string(),
+                line,
+                text: None,
+                generated: true,
+            };
+        }
+        let name = match source.path() {
+            Some(path) => path.display().to_string(),
+            None => module.to_string(),
+        };
+        let (line, text) = match source.line(line) {
+            Some((line, text)) => (line, Some(text)),
+            None => (line, None),
+        };
+        Location {
+            name,
+            line,
+            text,
+            generated: false,
+        }
+    }
+}
+
+impl<'a> Location<'a> {
+    // `width` is the widest line number in the report, so that every
+    // gutter lines up.
+    fn snippet(&self, out: &mut String, function: Option<&str>, width: usize, paint: Paint) {
+        let pad = " ".repeat(width);
+        let _ = write!(out, "{}{} {}:{}", pad, paint.paint("1;34", "-->"), self.name, self.line);
+        if self.generated {
+            out.push_str(" (generated)");
+        }
+        if let Some(function) = function {
+            let _ = write!(out, " in {}", function);
+        }
+        out.push('\n');
+        if let Some(text) = self.text {
+            let gutter = paint.paint("1;34", &format!("{} |", pad));
+            let number = format!("{:>width$} |", self.line, width = width);
+            let _ = writeln!(out, "{}", gutter);
+            let _ = writeln!(out, "{} {}", paint.paint("1;34", &number), text);
+            let _ = writeln!(out, "{}", gutter);
+        }
+    }
+
+    fn json(&self, module: &str) -> String {
+        format!(
+            "\"module\":{},\"file\":{},\"line\":{},\"generated\":{},\"source\":{}",
+            json_string(module),
+            json_string(&self.name),
+            self.line,
+            self.generated,
+            self.text.map_or("null".to_string(), json_string)
+        )
+    }
+}
+
+fn gutter_width(locations: &[Location]) -> usize {
+    locations
+        .iter()
+        .map(|location| location.line.to_string().len())
+        .max()
+        .unwrap_or(1)
+}
+
+fn header(out: &mut String, message: &str, paint: Paint) {
+    let _ = writeln!(
+        out,
+        "{}{}",
+        paint.paint("1;31", "error"),
+        paint.paint("1", &format!(": {}", message))
+    );
+}
+
+fn json_string(text: &str) -> String {
+    let mut out = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
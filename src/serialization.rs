@@ -0,0 +1,644 @@
+use std::error;
+use std::fmt;
+
+use serde::de::value::{MapDeserializer, SeqDeserializer, StringDeserializer};
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, Visitor};
+use serde::ser::{self, Serialize};
+
+use errors::WrenError;
+use foreign::{IntoSlot, Slots, MAX_EXACT};
+use value::Value;
+use vm::{CallHandle, Handle, Wren};
+
+/// Serializes `value` into a new Wren value, ready to be passed to
+/// `Wren::call`.
+///
+/// Structs and maps become Wren maps, sequences and tuples become lists, and
+/// enums are externally tagged: unit variants are strings, the others maps
+/// with a single entry. Byte buffers become strings.
+///
+/// Wren has no slot API for maps, so they are built by calling into the VM.
+/// This means it can't be used from inside a foreign method.
+pub fn to_wren<T: Serialize + ?Sized>(vm: &mut Wren, value: &T) -> Result<Value, WrenError> {
+    let data = value.serialize(DataSerializer).map_err(conversion)?;
+    let mut maps = Maps::take(vm)?;
+    let value = maps.build(vm, data);
+    vm.maps = Some(maps);
+    value
+}
+
+/// Deserializes a Wren value, such as a `Value` or a `Handle` returned by
+/// `Wren::call`, using the same mapping as `to_wren`.
+///
+/// Like `to_wren`, this calls into the VM to read maps and can't be used
+/// from inside a foreign method.
+pub fn from_wren<T: DeserializeOwned, V: IntoSlot>(vm: &mut Wren, value: V) -> Result<T, WrenError> {
+    vm.usable()?;
+    let value: Value = unsafe {
+        let _entered = vm.enter();
+        let mut slots = Slots::new(vm.raw());
+        slots.ensure(1);
+        slots.set(0, value).map_err(WrenError::Conversion)?;
+        slots.get(0).map_err(WrenError::Conversion)?
+    };
+    let mut maps = Maps::take(vm)?;
+    let data = maps.read(vm, value);
+    vm.maps = Some(maps);
+    T::deserialize(data?).map_err(conversion)
+}
+
+fn conversion(error: DataError) -> WrenError {
+    WrenError::Conversion(error.0)
+}
+
+// The serde data model, as far as Wren is concerned.
+#[derive(Debug)]
+enum Data {
+    Null,
+    Bool(bool),
+    Num(f64),
+    Str(String),
+    Bytes(Vec<u8>),
+    List(Vec<Data>),
+    Map(Vec<(Data, Data)>),
+}
+
+// Everything needed to create and inspect Wren maps.
+pub(crate) struct Maps {
+    class: Handle,
+    new: CallHandle,
+    is: CallHandle,
+    insert: CallHandle,
+    keys: CallHandle,
+    to_list: CallHandle,
+    get: CallHandle,
+}
+
+impl Maps {
+    // The VM's cached handles, which it gets back once the conversion is
+    // done.
+    fn take(vm: &mut Wren) -> Result<Maps, WrenError> {
+        if let Some(maps) = vm.maps.take() {
+            return Ok(maps);
+        }
+        Ok(Maps {
+            class: vm.get_class("main", "Map")?,
+            new: vm.make_call("new()")?,
+            is: vm.make_call("is(_)")?,
+            insert: vm.make_call("[_]=(_)")?,
+            keys: vm.make_call("keys")?,
+            to_list: vm.make_call("toList")?,
+            get: vm.make_call("[_]")?,
+        })
+    }
+
+    fn build(&mut self, vm: &mut Wren, data: Data) -> Result<Value, WrenError> {
+        Ok(match data {
+            Data::Null => Value::Null,
+            Data::Bool(b) => Value::Bool(b),
+            Data::Num(n) => Value::Num(n),
+            Data::Str(s) => Value::String(s),
+            Data::Bytes(bytes) => Value::Bytes(bytes),
+            Data::List(items) => Value::List(
+                items
+                    .into_iter()
+                    .map(|item| self.build(vm, item))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Data::Map(entries) => {
+                let map: Handle = vm.call(&self.class, &self.new, ())?;
+                for (key, value) in entries {
+                    let key = self.build(vm, key)?;
+                    let value = self.build(vm, value)?;
+                    vm.call::<_, _, ()>(&map, &self.insert, (key, value))?;
+                }
+                Value::Unknown(map)
+            }
+        })
+    }
+
+    fn read(&mut self, vm: &mut Wren, value: Value) -> Result<Data, WrenError> {
+        Ok(match value {
+            Value::Null => Data::Null,
+            Value::Bool(b) => Data::Bool(b),
+            Value::Num(n) => Data::Num(n),
+            Value::String(s) => Data::Str(s),
+            Value::Bytes(bytes) => Data::Bytes(bytes),
+            Value::List(items) => Data::List(
+                items
+                    .into_iter()
+                    .map(|item| self.read(vm, item))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Value::Unknown(ref handle) if vm.call(handle, &self.is, (&self.class,))? => {
+                let keys: Handle = vm.call(handle, &self.keys, ())?;
+                let keys: Vec<Value> = vm.call(&keys, &self.to_list, ())?;
+                let mut entries = Vec::with_capacity(keys.len());
+                for key in keys {
+                    let value = vm.call(handle, &self.get, (&key,))?;
+                    entries.push((self.read(vm, key)?, self.read(vm, value)?));
+                }
+                Data::Map(entries)
+            }
+            other => {
+                return Err(WrenError::Conversion(format!(
+                    "can't deserialize a {}, only maps, lists and primitives",
+                    other.type_name()
+                )))
+            }
+        })
+    }
+}
+
+#[derive(Debug)]
+struct DataError(String);
+
+impl fmt::Display for DataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl error::Error for DataError {}
+
+fn inexact<T: fmt::Display>(v: T) -> DataError {
+    DataError(format!("{} is out of range for a Wren number", v))
+}
+
+impl ser::Error for DataError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        DataError(msg.to_string())
+    }
+}
+
+impl de::Error for DataError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        DataError(msg.to_string())
+    }
+}
+
+struct DataSerializer;
+
+// Collects the elements of sequences, tuples and tuple variants.
+struct SeqSerializer {
+    variant: Option<&'static str>,
+    items: Vec<Data>,
+}
+
+// Collects the entries of maps, structs and struct variants.
+struct MapSerializer {
+    variant: Option<&'static str>,
+    entries: Vec<(Data, Data)>,
+    key: Option<Data>,
+}
+
+fn tagged(variant: &'static str, data: Data) -> Data {
+    Data::Map(vec![(Data::Str(variant.into()), data)])
+}
+
+impl ser::Serializer for DataSerializer {
+    type Ok = Data;
+    type Error = DataError;
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = SeqSerializer;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = MapSerializer;
+    type SerializeStructVariant = MapSerializer;
+
+    fn serialize_bool(self, v: bool) -> Result<Data, DataError> {
+        Ok(Data::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Data, DataError> {
+        Ok(Data::Num(v.into()))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Data, DataError> {
+        Ok(Data::Num(v.into()))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Data, DataError> {
+        Ok(Data::Num(v.into()))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Data, DataError> {
+        let n = v as f64;
+        if n.abs() > MAX_EXACT || n as i64 != v {
+            return Err(inexact(v));
+        }
+        Ok(Data::Num(n))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Data, DataError> {
+        Ok(Data::Num(v.into()))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Data, DataError> {
+        Ok(Data::Num(v.into()))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Data, DataError> {
+        Ok(Data::Num(v.into()))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Data, DataError> {
+        let n = v as f64;
+        if n > MAX_EXACT || n as u64 != v {
+            return Err(inexact(v));
+        }
+        Ok(Data::Num(n))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Data, DataError> {
+        Ok(Data::Num(v.into()))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Data, DataError> {
+        Ok(Data::Num(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Data, DataError> {
+        Ok(Data::Str(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Data, DataError> {
+        Ok(Data::Str(v.into()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Data, DataError> {
+        Ok(Data::Bytes(v.into()))
+    }
+
+    fn serialize_none(self) -> Result<Data, DataError> {
+        Ok(Data::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Data, DataError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Data, DataError> {
+        Ok(Data::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Data, DataError> {
+        Ok(Data::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Data, DataError> {
+        Ok(Data::Str(variant.into()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Data, DataError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Data, DataError> {
+        Ok(tagged(variant, value.serialize(self)?))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer, DataError> {
+        Ok(SeqSerializer {
+            variant: None,
+            items: Vec::with_capacity(len.unwrap_or(0)),
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer, DataError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, DataError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, DataError> {
+        Ok(SeqSerializer {
+            variant: Some(variant),
+            items: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<MapSerializer, DataError> {
+        Ok(MapSerializer {
+            variant: None,
+            entries: Vec::with_capacity(len.unwrap_or(0)),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<MapSerializer, DataError> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<MapSerializer, DataError> {
+        Ok(MapSerializer {
+            variant: Some(variant),
+            entries: Vec::with_capacity(len),
+            key: None,
+        })
+    }
+}
+
+impl SeqSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), DataError> {
+        self.items.push(value.serialize(DataSerializer)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Data, DataError> {
+        let list = Data::List(self.items);
+        Ok(match self.variant {
+            Some(variant) => tagged(variant, list),
+            None => list,
+        })
+    }
+}
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = Data;
+    type Error = DataError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), DataError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Data, DataError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = Data;
+    type Error = DataError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), DataError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Data, DataError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = Data;
+    type Error = DataError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), DataError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Data, DataError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SeqSerializer {
+    type Ok = Data;
+    type Error = DataError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), DataError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Data, DataError> {
+        self.finish()
+    }
+}
+
+impl MapSerializer {
+    fn field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), DataError> {
+        let value = value.serialize(DataSerializer)?;
+        self.entries.push((Data::Str(key.into()), value));
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Data, DataError> {
+        let map = Data::Map(self.entries);
+        Ok(match self.variant {
+            Some(variant) => tagged(variant, map),
+            None => map,
+        })
+    }
+}
+
+impl ser::SerializeMap for MapSerializer {
+    type Ok = Data;
+    type Error = DataError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), DataError> {
+        self.key = Some(key.serialize(DataSerializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), DataError> {
+        let key = self.key
+            .take()
+            .ok_or_else(|| DataError("map value without a key".into()))?;
+        self.entries.push((key, value.serialize(DataSerializer)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Data, DataError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for MapSerializer {
+    type Ok = Data;
+    type Error = DataError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), DataError> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<Data, DataError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for MapSerializer {
+    type Ok = Data;
+    type Error = DataError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), DataError> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<Data, DataError> {
+        self.finish()
+    }
+}
+
+impl<'de> IntoDeserializer<'de, DataError> for Data {
+    type Deserializer = Data;
+
+    fn into_deserializer(self) -> Data {
+        self
+    }
+}
+
+// Whole numbers up to 2^53 are exact, so they are handed to visitors as
+// integers, which also lets them range check. Larger ones may have been
+// rounded, and integer visitors refuse them like the slot conversions do.
+fn visit_num<'de, V: Visitor<'de>>(n: f64, visitor: V) -> Result<V::Value, DataError> {
+    if n.fract() == 0.0 && (0.0..=MAX_EXACT).contains(&n) {
+        visitor.visit_u64(n as u64)
+    } else if n.fract() == 0.0 && (-MAX_EXACT..0.0).contains(&n) {
+        visitor.visit_i64(n as i64)
+    } else {
+        visitor.visit_f64(n)
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Data {
+    type Error = DataError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DataError> {
+        match self {
+            Data::Null => visitor.visit_unit(),
+            Data::Bool(b) => visitor.visit_bool(b),
+            Data::Num(n) => visit_num(n, visitor),
+            Data::Str(s) => visitor.visit_string(s),
+            Data::Bytes(bytes) => visitor.visit_byte_buf(bytes),
+            Data::List(items) => {
+                let mut seq = SeqDeserializer::new(items.into_iter());
+                let value = visitor.visit_seq(&mut seq)?;
+                seq.end()?;
+                Ok(value)
+            }
+            Data::Map(entries) => {
+                let mut map = MapDeserializer::new(entries.into_iter());
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            }
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DataError> {
+        match self {
+            Data::Null => visitor.visit_none(),
+            data => visitor.visit_some(data),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, DataError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DataError> {
+        match self {
+            Data::Str(variant) => {
+                let variant: StringDeserializer<DataError> = variant.into_deserializer();
+                visitor.visit_enum(variant)
+            }
+            Data::Map(mut entries) => {
+                if entries.len() != 1 {
+                    return Err(DataError(
+                        "expected a map with a single entry for an enum".into(),
+                    ));
+                }
+                let (variant, value) = entries.pop().unwrap();
+                visitor.visit_enum(Enum { variant, value })
+            }
+            other => Err(DataError(format!("expected an enum, got {:?}", other))),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+struct Enum {
+    variant: Data,
+    value: Data,
+}
+
+impl<'de> de::EnumAccess<'de> for Enum {
+    type Error = DataError;
+    type Variant = Data;
+
+    fn variant_seed<S: DeserializeSeed<'de>>(self, seed: S) -> Result<(S::Value, Data), DataError> {
+        let variant = seed.deserialize(self.variant)?;
+        Ok((variant, self.value))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for Data {
+    type Error = DataError;
+
+    fn unit_variant(self) -> Result<(), DataError> {
+        de::Deserialize::deserialize(self)
+    }
+
+    fn newtype_variant_seed<S: DeserializeSeed<'de>>(self, seed: S) -> Result<S::Value, DataError> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, DataError> {
+        de::Deserializer::deserialize_seq(self, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DataError> {
+        de::Deserializer::deserialize_map(self, visitor)
+    }
+}
@@ -1,5 +1,7 @@
+use std::any::Any;
 use std::ffi::{CStr, CString};
 use std::os::raw::c_char;
+use std::ptr;
 
 pub unsafe fn c_string(input: *const c_char) -> CString {
     CStr::from_ptr(input).to_owned()
@@ -10,5 +12,30 @@ pub fn from_str(input: &str) -> CString {
 }
 
 pub unsafe fn lossy_string(input: *const c_char) -> String {
+    if input.is_null() {
+        return String::new();
+    }
     CStr::from_ptr(input).to_string_lossy().into()
 }
+
+// Wren takes ownership of strings handed back from callbacks like
+// `loadModuleFn` and frees them with its reallocate function. So they have to
+// be copied into a buffer from the same allocator.
+pub unsafe fn wren_owned_string(input: CString) -> *mut c_char {
+    let bytes = input.as_bytes_with_nul();
+    let ptr = ::memory::allocate(bytes.len()) as *mut c_char;
+    if !ptr.is_null() {
+        ptr::copy_nonoverlapping(bytes.as_ptr() as *const c_char, ptr, bytes.len());
+    }
+    ptr
+}
+
+pub fn panic_message(payload: &Box<dyn Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "Box<Any>".into()
+    }
+}
@@ -0,0 +1,102 @@
+use foreign::{FromSlot, IntoSlot, Slots};
+use std::os::raw::c_int;
+use vm::Handle;
+use wren_sys;
+use wren_sys::WrenType;
+
+/// Any Wren value, for foreign methods that accept more than one type.
+///
+/// Strings that are valid UTF-8 become `String`, others `Bytes`. Foreign
+/// objects and everything without a Rust counterpart (maps, functions,
+/// instances of Wren classes...) are kept as handles.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Num(f64),
+    String(String),
+    Bytes(Vec<u8>),
+    List(Vec<Value>),
+    Foreign(Handle),
+    Unknown(Handle),
+}
+
+impl Value {
+    pub fn is_null(&self) -> bool {
+        matches!(*self, Value::Null)
+    }
+
+    /// The name of the Wren type, for error messages.
+    pub fn type_name(&self) -> &'static str {
+        match *self {
+            Value::Null => "Null",
+            Value::Bool(_) => "Bool",
+            Value::Num(_) => "Num",
+            Value::String(_) | Value::Bytes(_) => "String",
+            Value::List(_) => "List",
+            Value::Foreign(_) => "foreign object",
+            Value::Unknown(_) => "object",
+        }
+    }
+}
+
+impl<'vm> Slots<'vm> {
+    pub fn get_value(&mut self, slot: usize) -> Result<Value, String> {
+        self.get(slot)
+    }
+
+    pub fn set_value(&mut self, slot: usize, value: &Value) -> Result<(), String> {
+        self.set(slot, value)
+    }
+}
+
+impl FromSlot for Value {
+    fn from_slot(slots: &mut Slots, slot: usize) -> Result<Value, String> {
+        Ok(match slots.slot_type(slot)? {
+            WrenType::WREN_TYPE_NULL => Value::Null,
+            WrenType::WREN_TYPE_BOOL => Value::Bool(slots.get(slot)?),
+            WrenType::WREN_TYPE_NUM => Value::Num(slots.get(slot)?),
+            WrenType::WREN_TYPE_STRING => match String::from_utf8(slots.bytes(slot)?) {
+                Ok(s) => Value::String(s),
+                Err(e) => Value::Bytes(e.into_bytes()),
+            },
+            WrenType::WREN_TYPE_LIST => Value::List(slots.get(slot)?),
+            WrenType::WREN_TYPE_FOREIGN => Value::Foreign(slots.get(slot)?),
+            WrenType::WREN_TYPE_UNKNOWN => Value::Unknown(slots.get(slot)?),
+        })
+    }
+}
+
+impl IntoSlot for &Value {
+    fn into_slot(self, slots: &mut Slots, slot: usize) -> Result<(), String> {
+        match *self {
+            Value::Null => slots.set(slot, ()),
+            Value::Bool(b) => slots.set(slot, b),
+            Value::Num(n) => slots.set(slot, n),
+            Value::String(ref s) => slots.set(slot, s.as_str()),
+            Value::Bytes(ref bytes) => slots.set(slot, bytes.as_slice()),
+            Value::List(ref values) => {
+                let list = slots.check(slot)?;
+                unsafe { wren_sys::wrenSetSlotNewList(slots.raw(), list) };
+                slots.scratch(|slots, element| {
+                    for (i, value) in values.iter().enumerate() {
+                        slots
+                            .set(element, value)
+                            .map_err(|e| format!("list element {}: {}", i, e))?;
+                        unsafe {
+                            wren_sys::wrenInsertInList(slots.raw(), list, -1, element as c_int)
+                        };
+                    }
+                    Ok(())
+                })
+            }
+            Value::Foreign(ref handle) | Value::Unknown(ref handle) => slots.set(slot, handle),
+        }
+    }
+}
+
+impl IntoSlot for Value {
+    fn into_slot(self, slots: &mut Slots, slot: usize) -> Result<(), String> {
+        slots.set(slot, &self)
+    }
+}
@@ -1,17 +1,34 @@
 use errors::*;
+use std::any::Any;
 use std::rc::Rc;
-use std::cell::RefCell;
+use std::cell::Cell;
+use std::collections::HashSet;
 use std::os::raw::{c_char, c_int, c_void};
-use errors::WrenError;
 use std::ffi::{CStr, CString};
+use std::fmt;
 use std::mem;
-use wren_sys::{WrenConfiguration, WrenErrorType, WrenInterpretResult, WrenVM, wrenFreeVM,
-               wrenGetUserData, wrenInitConfiguration, wrenNewVM, wrenSetUserData};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use wren_sys::{WrenConfiguration, WrenErrorType, WrenHandle, WrenInterpretResult, WrenVM,
+               wrenCollectGarbage, wrenFreeVM, wrenGetSlotHandle, wrenGetUserData,
+               wrenGetVariable, wrenInitConfiguration, wrenMakeCallHandle, wrenNewVM,
+               wrenReleaseHandle, wrenSetSlotHandle, wrenSetUserData, wrensys_protected_call,
+               wrensys_protected_interpret};
 use foreign::*;
+use memory::{self, Memory};
+pub use memory::MemoryStats;
+use module::*;
+use output::WriteFn;
+use report::Report;
+use util::*;
 
 pub struct WrenBuilder {
     inner: WrenConfiguration,
     foreigns: Foreign,
+    loader: Option<Box<dyn ModuleLoader>>,
+    write_fn: Option<Box<WriteFn>>,
+    context: Option<Box<dyn Any>>,
+    memory_limit: Option<usize>,
 }
 
 unsafe extern "C" fn error_callback(
@@ -21,22 +38,74 @@ unsafe extern "C" fn error_callback(
     line: c_int,
     message: *const c_char,
 ) {
-    let error: Result<Trace, WrenError> = match ty {
-        WrenErrorType::WREN_ERROR_STACK_TRACE => Ok(Trace::new(message, module, line)),
-        _ => Err(WrenError::new(ty, module, line, message)),
-    };
-    let user_data = wrenGetUserData(vm) as *const UserData;
-    ((*user_data).error_cb)(error);
+    let _held = memory::hold();
+    let user_data = wrenGetUserData(vm) as *mut UserData;
+    (*user_data).errors.report(ty, module, line, message);
 }
 
-unsafe extern "C" fn write_callback(_vm: *mut WrenVM, text: *const c_char) {
+unsafe extern "C" fn write_callback(vm: *mut WrenVM, text: *const c_char) {
+    let _held = memory::hold();
     let output = CStr::from_ptr(text).to_string_lossy();
-    print!("{}", output);
+    let user_data = wrenGetUserData(vm) as *mut UserData;
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| ((*user_data).write_fn)(&output)));
+}
+
+unsafe extern "C" fn load_module_callback(vm: *mut WrenVM, name: *const c_char) -> *mut c_char {
+    let _held = memory::hold();
+    let user_data = &mut *(wrenGetUserData(vm) as *mut UserData);
+    let name = lossy_string(name);
+    let pending = match user_data.pending {
+        Some((ref module, _)) if *module == name => user_data.pending.take(),
+        _ => None,
+    };
+    let (loaded, path) = match pending {
+        Some((_, source)) => (Some(source), None),
+        None => {
+            let loader = &mut user_data.loader;
+            panic::catch_unwind(AssertUnwindSafe(|| match *loader {
+                Some(ref mut loader) => match loader.load(&name) {
+                    Some(source) => (Some(source), loader.locate(&name)),
+                    None => (None, None),
+                },
+                None => (None, None),
+            })).unwrap_or((None, None))
+        }
+    };
+    // Modules with Rust bindings get their declarations generated, followed
+    // by whatever the loader has for them.
+    let generated = user_data.foreigns.render_module(&name);
+    let source = match (&generated, &loaded) {
+        (Some(generated), Some(loaded)) => Some(generated.clone() + loaded),
+        _ => generated.clone().or_else(|| loaded.clone()),
+    };
+    let source = match source.and_then(|source| CString::new(source).ok()) {
+        Some(source) => source,
+        None => return ptr::null_mut(),
+    };
+    user_data.errors.importing(&name);
+    user_data.modules.insert(ModuleSource::new(
+        name,
+        path,
+        loaded.unwrap_or_default(),
+        generated.as_deref(),
+    ));
+    wren_owned_string(source)
 }
 
 pub struct UserData {
     pub foreigns: Foreign,
-    error_cb: Box<Fn(Result<Trace, WrenError>)>,
+    pub(crate) errors: ErrorAccumulator,
+    loader: Option<Box<dyn ModuleLoader>>,
+    write_fn: Box<WriteFn>,
+    pub(crate) context: Option<Box<dyn Any>>,
+    pub(crate) memory: Rc<Memory>,
+    pub(crate) modules: ModuleRegistry,
+    // Source for the next import of a module, from `Wren::interpret_module`.
+    pending: Option<(String, String)>,
+    // Handles not released yet. Wren expects all of them to be released
+    // before the VM is freed.
+    handles: HashSet<*mut WrenHandle>,
+    alive: Rc<Cell<bool>>,
 }
 
 impl WrenBuilder {
@@ -48,12 +117,84 @@ impl WrenBuilder {
         };
         WrenBuilder {
             foreigns: Default::default(),
+            loader: None,
+            write_fn: None,
+            context: None,
+            memory_limit: None,
             inner,
         }
     }
 
-    pub fn bind_class<T: WrenClass>(mut self) -> Self {
+    /// Sets where script output goes. By default it is printed to stdout.
+    pub fn write_fn<F: FnMut(&str) + 'static>(mut self, write_fn: F) -> Self {
+        self.write_fn = Some(Box::new(write_fn));
+        self
+    }
+
+    /// Stores application state in the VM, for foreign methods to reach
+    /// with `Slots::context` and `Slots::context_mut`.
+    pub fn with_context<C: 'static>(mut self, context: C) -> Self {
+        self.context = Some(Box::new(context));
+        self
+    }
+
+    /// Caps the memory the VM can hold at `bytes`.
+    ///
+    /// A script that needs more memory than that, after collecting garbage,
+    /// is stopped in the middle of the allocation and `interpret` or `call`
+    /// returns `WrenError::OutOfMemory`. Wren can't be resumed from there, so
+    /// the VM is done for: everything that would run it again returns
+    /// `OutOfMemory` as well, and it is only good for dropping.
+    ///
+    /// Foreign methods aren't stopped in the middle. Memory they allocate
+    /// past the limit aborts the fiber at the next foreign method or
+    /// constructor it calls, or when it next allocates, whichever is first.
+    pub fn memory_limit(mut self, bytes: usize) -> Self {
+        self.memory_limit = Some(bytes);
+        self
+    }
+
+    /// How many bytes Wren allocates before its first garbage collection.
+    ///
+    /// With a `memory_limit`, this is at most half the limit.
+    pub fn initial_heap_size(mut self, bytes: usize) -> Self {
+        self.inner.initialHeapSize = bytes;
+        self
+    }
+
+    /// The fewest bytes Wren lets the heap shrink to between collections.
+    ///
+    /// With a `memory_limit`, this is at most half the limit.
+    pub fn min_heap_size(mut self, bytes: usize) -> Self {
+        self.inner.minHeapSize = bytes;
+        self
+    }
+
+    /// How much the heap may grow after a collection before the next one, as
+    /// a percentage of the memory still in use. Anything past `c_int::MAX`
+    /// is treated as `c_int::MAX`.
+    pub fn heap_growth_percent(mut self, percent: u32) -> Self {
+        self.inner.heapGrowthPercent = percent.min(c_int::MAX as u32) as c_int;
+        self
+    }
+
+    pub fn module_loader<L: ModuleLoader + 'static>(mut self, loader: L) -> Self {
+        self.loader = Some(Box::new(loader));
+        self
+    }
+
+    pub fn bind_class<T: WrenClass + WrenConstruct>(mut self) -> Self {
+        self.foreigns.bind_class::<T>();
+        self
+    }
+
+    /// Binds `T` and every method listed by its `WrenMethods` impl.
+    pub fn bind<T: WrenMethods + WrenConstruct>(mut self) -> Self {
         self.foreigns.bind_class::<T>();
+        for bound in T::methods() {
+            self.foreigns
+                .bind_method(T::MODULE, T::CLASS, bound.is_static, bound.name, bound.method);
+        }
         self
     }
 
@@ -70,72 +211,536 @@ impl WrenBuilder {
         self
     }
 
+    /// Binds a closure as a foreign method. Unlike a `ForeignMethod` it can
+    /// capture state, which lives as long as the VM.
+    ///
+    /// `signature` is the parameter part of the signature, such as `(_,_)`.
+    /// An `Err` returned by the closure aborts the fiber with that message.
+    ///
+    /// At most 128 methods can be bound to closures, binding the same method
+    /// again replaces its closure. Going past that returns
+    /// `WrenError::TooManyClosures`.
+    pub fn bind_closure<F>(
+        mut self,
+        module: &str,
+        class_name: &str,
+        is_static: bool,
+        name: &str,
+        signature: &str,
+        method: F,
+    ) -> Result<Self, WrenError>
+    where
+        F: Fn(&mut Slots) -> Result<(), String> + 'static,
+    {
+        self.foreigns
+            .bind_closure(module, class_name, is_static, name, signature, method)?;
+        Ok(self)
+    }
+
     pub fn build(self) -> Wren {
         let mut inner = self.inner;
 
-        // build error callback. It will be fetched from the UserData and will
-        // be responsible for updating the error refcell.
-        let error = Rc::new(RefCell::new(None));
-        let error_ref = error.clone();
-        let error_cb = Box::new(move |err: Result<Trace, WrenError>| {
-            let mut err_mut = error_ref.borrow_mut();
-            #[allow(unused_variables)]
-            match err {
-                Ok(trace) => match *err_mut {
-                    Some(WrenError::Runtime {
-                        ref message,
-                        ref mut stack,
-                    }) => stack.push(trace),
-                    _ => panic!("got a trace without a runtime error"),
-                },
-                Err(err) => *err_mut = Some(err),
-            }
-        });
-
+        let alive = Rc::new(Cell::new(true));
+        let memory = Rc::new(Memory::new(self.memory_limit));
         let user_data = Box::new(UserData {
             foreigns: self.foreigns,
-            error_cb,
+            errors: Default::default(),
+            loader: self.loader,
+            write_fn: self.write_fn
+                .unwrap_or_else(|| Box::new(|text: &str| print!("{}", text))),
+            context: self.context,
+            memory: memory.clone(),
+            modules: Default::default(),
+            pending: None,
+            handles: Default::default(),
+            alive: alive.clone(),
         });
 
         inner.errorFn = Some(error_callback);
         inner.writeFn = Some(write_callback);
         inner.bindForeignClassFn = Some(bind_foreign_class);
         inner.bindForeignMethodFn = Some(bind_foreign_method);
+        inner.loadModuleFn = Some(load_module_callback);
+        inner.reallocateFn = Some(memory::reallocate);
+        // Collect garbage well before the limit, so that it is only reached
+        // by memory the script actually holds on to.
+        if let Some(limit) = self.memory_limit {
+            inner.initialHeapSize = inner.initialHeapSize.min(limit / 2);
+            inner.minHeapSize = inner.minHeapSize.min(limit / 2);
+        }
 
-        let sys_vm = unsafe { wrenNewVM(&mut inner as *mut WrenConfiguration) };
+        let sys_vm = {
+            let _entered = memory::enter(&memory);
+            unsafe { wrenNewVM(&mut inner as *mut WrenConfiguration) }
+        };
+        memory.attach(sys_vm);
         unsafe { wrenSetUserData(sys_vm, Box::into_raw(user_data) as *mut c_void) };
 
-        let wren = Wren {
+        let mut wren = Wren {
             inner: sys_vm,
-            error: error,
+            memory,
+            alive,
+            #[cfg(feature = "serde")]
+            maps: None,
         };
-
+        // Wren creates `main` on the first interpret. Doing that right away
+        // means it always exists for variable lookups.
+        let _ = wren.run(&from_str(""));
         wren
     }
 }
 
 pub struct Wren {
     inner: *mut WrenVM,
-    error: Rc<RefCell<Option<WrenError>>>,
+    memory: Rc<Memory>,
+    alive: Rc<Cell<bool>>,
+    // Looked up on the first serialization and kept for the next ones.
+    #[cfg(feature = "serde")]
+    pub(crate) maps: Option<::serialization::Maps>,
+}
+
+// Owns a WrenHandle and releases it when dropped. Handles may outlive the VM
+// that created them, so the VM releases the ones still around before it is
+// freed, and they are skipped here from then on.
+struct RawHandle {
+    vm: *mut WrenVM,
+    raw: *mut WrenHandle,
+    alive: Rc<Cell<bool>>,
+}
+
+impl RawHandle {
+    unsafe fn new(vm: *mut WrenVM, raw: *mut WrenHandle) -> RawHandle {
+        let user_data = wrenGetUserData(vm) as *mut UserData;
+        (*user_data).handles.insert(raw);
+        RawHandle {
+            vm,
+            raw,
+            alive: (*user_data).alive.clone(),
+        }
+    }
+
+    fn is_valid_for(&self, vm: *mut WrenVM) -> bool {
+        self.alive.get() && self.vm == vm
+    }
+}
+
+impl Drop for RawHandle {
+    fn drop(&mut self) {
+        if !self.alive.get() {
+            return;
+        }
+        unsafe {
+            let user_data = wrenGetUserData(self.vm) as *mut UserData;
+            if (*user_data).handles.remove(&self.raw) {
+                wrenReleaseHandle(self.vm, self.raw);
+            }
+        }
+    }
+}
+
+/// A persistent reference to a Wren object.
+///
+/// The object is kept alive for as long as the handle (or any of its clones)
+/// exists. Handles can be stored in foreign objects, so keep in mind that a
+/// handle referencing its owner forms a cycle that is only broken when the VM
+/// is freed.
+#[derive(Clone)]
+pub struct Handle {
+    inner: Rc<RawHandle>,
+}
+
+impl Handle {
+    pub fn is_alive(&self) -> bool {
+        self.inner.alive.get()
+    }
+}
+
+impl fmt::Debug for Handle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Handle")
+            .field("raw", &self.inner.raw)
+            .field("alive", &self.is_alive())
+            .finish()
+    }
+}
+
+impl FromSlot for Handle {
+    fn from_slot(slots: &mut Slots, slot: usize) -> Result<Handle, String> {
+        let index = slots.check(slot)?;
+        let vm = slots.raw();
+        unsafe {
+            Ok(Handle {
+                inner: Rc::new(RawHandle::new(vm, wrenGetSlotHandle(vm, index))),
+            })
+        }
+    }
+}
+
+impl IntoSlot for &Handle {
+    fn into_slot(self, slots: &mut Slots, slot: usize) -> Result<(), String> {
+        let index = slots.check(slot)?;
+        if !self.inner.is_valid_for(slots.raw()) {
+            return Err("handle does not belong to this VM".into());
+        }
+        unsafe { wrenSetSlotHandle(slots.raw(), index, self.inner.raw) };
+        Ok(())
+    }
+}
+
+impl IntoSlot for Handle {
+    fn into_slot(self, slots: &mut Slots, slot: usize) -> Result<(), String> {
+        (&self).into_slot(slots, slot)
+    }
+}
+
+/// A method signature that can be invoked with `Wren::call`, such as
+/// `update(_,_)`, `count` or `[_]=(_)`.
+pub struct CallHandle {
+    handle: RawHandle,
+    signature: String,
+    arity: usize,
+}
+
+impl CallHandle {
+    pub fn signature(&self) -> &str {
+        &self.signature
+    }
+
+    pub fn arity(&self) -> usize {
+        self.arity
+    }
+}
+
+/// Arguments to `Wren::call`, implemented for tuples of `IntoSlot` values.
+pub trait CallArgs {
+    fn count(&self) -> usize;
+    fn into_slots(self, slots: &mut Slots, start: usize) -> Result<(), String>;
+}
+
+macro_rules! impl_call_args {
+    ($count:expr) => (
+        impl_call_args!($count;);
+    );
+    ($count:expr; $($name:ident : $idx:tt),*) => (
+        impl<$($name: IntoSlot),*> CallArgs for ($($name,)*) {
+            fn count(&self) -> usize {
+                $count
+            }
+
+            #[allow(unused_variables)]
+            fn into_slots(self, slots: &mut Slots, start: usize) -> Result<(), String> {
+                $(slots.set(start + $idx, self.$idx)?;)*
+                Ok(())
+            }
+        }
+    )
 }
 
+impl_call_args!(0);
+impl_call_args!(1; A: 0);
+impl_call_args!(2; A: 0, B: 1);
+impl_call_args!(3; A: 0, B: 1, C: 2);
+impl_call_args!(4; A: 0, B: 1, C: 2, D: 3);
+impl_call_args!(5; A: 0, B: 1, C: 2, D: 3, E: 4);
+impl_call_args!(6; A: 0, B: 1, C: 2, D: 3, E: 4, F: 5);
+impl_call_args!(7; A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6);
+impl_call_args!(8; A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7);
+
 impl Wren {
     pub fn interpret<S: Into<Vec<u8>>>(&mut self, source: S) -> Result<(), WrenError> {
         let c_source = CString::new(source).unwrap();
-        let res = unsafe { ::wren_sys::wrenInterpret(self.inner, c_source.as_ptr()) };
-        match res {
-            WrenInterpretResult::WREN_RESULT_SUCCESS => Ok(()),
-            _ => Err(self.error.borrow_mut().take().unwrap()),
+        let source = c_source.to_string_lossy().into_owned();
+        self.user_data()
+            .modules
+            .insert(ModuleSource::new("main".into(), None, source, None));
+        self.run(&c_source)
+    }
+
+    /// Compiles and runs `source` as the module `name`, as though a script
+    /// had imported it. Other modules can then import it by that name.
+    ///
+    /// Wren can't add code to a module that is already loaded, so this
+    /// fails with `WrenError::DuplicateModule` for those, apart from `main`.
+    /// That includes Wren's own optional modules, such as `meta`, once a
+    /// script has imported them.
+    pub fn interpret_module<S: Into<String>>(&mut self, name: &str, source: S) -> Result<(), WrenError> {
+        if name == "main" {
+            return self.interpret(source.into());
+        }
+        if self.modules().contains(name) {
+            return Err(WrenError::DuplicateModule(name.into()));
+        }
+        self.user_data().pending = Some((name.into(), source.into()));
+        let import = CString::new(format!("import {}", string_literal(name))).unwrap();
+        let mut res = self.run(&import);
+        // The import is compiled into `main` too, but it isn't the caller's
+        // code and its lines aren't the ones in the registry.
+        if let Err(WrenError::Runtime { ref mut stack, .. }) = res {
+            stack.retain(|trace| trace.module != "main");
+        }
+        // Wren only asks for modules it hasn't loaded yet.
+        match self.user_data().pending.take() {
+            Some(_) if res.is_ok() => Err(WrenError::DuplicateModule(name.into())),
+            _ => res,
+        }
+    }
+
+    /// The source of every module loaded so far.
+    pub fn modules(&self) -> &ModuleRegistry {
+        unsafe { &(*(wrenGetUserData(self.inner) as *const UserData)).modules }
+    }
+
+    /// Renders `error` with the source of the modules it mentions.
+    pub fn report<'a>(&'a self, error: &'a WrenError) -> Report<'a> {
+        Report::new(error, self.modules())
+    }
+
+    fn run(&mut self, c_source: &CStr) -> Result<(), WrenError> {
+        self.usable()?;
+        let _entered = memory::enter(&self.memory);
+        self.begin();
+        let vm = self.inner;
+        // Escaping leaves `res` alone, `check` reports it as out of memory.
+        let mut res = WrenInterpretResult::WREN_RESULT_SUCCESS;
+        self.memory.protect(|| unsafe {
+            wrensys_protected_interpret(vm, c_source.as_ptr(), &mut res)
+        });
+        self.check(res)
+    }
+
+    /// Fails with `WrenError::InvalidSignature` for an empty signature or
+    /// one containing a NUL byte.
+    pub fn make_call(&mut self, signature: &str) -> Result<CallHandle, WrenError> {
+        if signature.is_empty() || signature.contains('\0') {
+            return Err(WrenError::InvalidSignature(signature.into()));
+        }
+        self.usable()?;
+        let c_signature = from_str(signature);
+        let _entered = memory::enter(&self.memory);
+        let raw = unsafe { wrenMakeCallHandle(self.inner, c_signature.as_ptr()) };
+        Ok(CallHandle {
+            handle: unsafe { RawHandle::new(self.inner, raw) },
+            signature: signature.into(),
+            arity: arity(signature),
+        })
+    }
+
+    /// Invokes `handle` on `receiver`, converting the return value to `T`.
+    pub fn call<R, A, T>(&mut self, receiver: R, handle: &CallHandle, args: A) -> Result<T, WrenError>
+    where
+        R: IntoSlot,
+        A: CallArgs,
+        T: FromSlot,
+    {
+        self.usable()?;
+        if !handle.handle.is_valid_for(self.inner) {
+            return Err(WrenError::InvalidHandle);
+        }
+        if args.count() != handle.arity {
+            return Err(WrenError::Arity {
+                signature: handle.signature.clone(),
+                expected: handle.arity,
+                got: args.count(),
+            });
+        }
+
+        let _entered = memory::enter(&self.memory);
+        let mut slots = unsafe { Slots::new(self.inner) };
+        slots.ensure(handle.arity + 1);
+        slots.set(0, receiver).map_err(WrenError::Conversion)?;
+        args.into_slots(&mut slots, 1)
+            .map_err(WrenError::Conversion)?;
+
+        self.begin();
+        let (vm, raw) = (self.inner, handle.handle.raw);
+        let mut res = WrenInterpretResult::WREN_RESULT_SUCCESS;
+        self.memory
+            .protect(|| unsafe { wrensys_protected_call(vm, raw, &mut res) });
+        self.check(res)?;
+        slots.get(0).map_err(WrenError::Conversion)
+    }
+
+    /// Reads the top-level variable `name` from `module`.
+    ///
+    /// Only modules that are already loaded are searched, looking a variable
+    /// up never imports anything. Wren has no way to assign a module
+    /// variable from outside the module, so there is no setter.
+    pub fn get_variable<T: FromSlot>(&mut self, module: &str, name: &str) -> Result<T, WrenError> {
+        self.probe_variable(module, name)?;
+        self.read_variable(module, name)
+    }
+
+    /// Looks up the class `name` declared in `module`, for use as the
+    /// receiver of static methods.
+    pub fn get_class(&mut self, module: &str, name: &str) -> Result<Handle, WrenError> {
+        let class: Handle = self.get_variable(module, name)?;
+        // Every module has the core classes, so `Class` is always there.
+        let metaclass: Handle = self.read_variable(module, "Class")?;
+        let is = self.make_call("is(_)")?;
+        if self.call(&class, &is, (&metaclass,))? {
+            Ok(class)
+        } else {
+            Err(WrenError::Conversion(format!("{} is not a class", name)))
         }
     }
+
+    /// Runs a full garbage collection, unless the VM ran out of memory.
+    pub fn collect_garbage(&mut self) {
+        if self.usable().is_err() {
+            return;
+        }
+        let _entered = memory::enter(&self.memory);
+        unsafe { wrenCollectGarbage(self.inner) };
+    }
+
+    /// How much memory the VM holds now, and has held since it was built.
+    pub fn memory_stats(&self) -> MemoryStats {
+        self.memory.stats()
+    }
+
+    /// The Wren declarations generated for the classes and methods bound in
+    /// `module`. This is what `import "<module>"` loads, so scripts don't
+    /// need to declare foreign classes themselves.
+    pub fn foreign_source(&mut self, module: &str) -> Option<String> {
+        self.user_data().foreigns.render_module(module)
+    }
+
+    // wrenGetVariable assumes that the module and variable exist, so make
+    // sure of that first by importing the variable in a throwaway scope.
+    // Importing a loaded module only binds the variable, nothing runs.
+    fn probe_variable(&mut self, module: &str, name: &str) -> Result<(), WrenError> {
+        let unknown = || {
+            WrenError::UnknownVariable {
+                module: module.into(),
+                name: name.into(),
+            }
+        };
+        let loaded = module == "main" || self.modules().contains(module);
+        let valid_name = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !loaded || !valid_name || module.contains('\0') {
+            return Err(unknown());
+        }
+        let probe = format!("{{\n  import {} for {}\n}}\n", string_literal(module), name);
+        // Running out of memory says nothing about the variable.
+        self.run(&from_str(&probe)).map_err(|error| match error {
+            WrenError::Compile { .. } | WrenError::Runtime { .. } => unknown(),
+            error => error,
+        })
+    }
+
+    fn read_variable<T: FromSlot>(&mut self, module: &str, name: &str) -> Result<T, WrenError> {
+        self.usable()?;
+        let _entered = memory::enter(&self.memory);
+        let mut slots = unsafe { Slots::new(self.inner) };
+        slots.ensure(1);
+        unsafe {
+            wrenGetVariable(self.inner, from_str(module).as_ptr(), from_str(name).as_ptr(), 0)
+        };
+        slots.get(0).map_err(WrenError::Conversion)
+    }
+
+    /// The context given to `WrenBuilder::with_context`, if it is a `C`.
+    pub fn context<C: 'static>(&mut self) -> Option<&mut C> {
+        self.user_data()
+            .context
+            .as_mut()
+            .and_then(|context| context.downcast_mut())
+    }
+
+    #[cfg(feature = "serde")]
+    pub(crate) fn raw(&self) -> *mut WrenVM {
+        self.inner
+    }
+
+    /// Charges allocations to this VM until the guard is dropped, for code
+    /// that uses `raw` directly.
+    #[cfg(feature = "serde")]
+    pub(crate) fn enter(&self) -> memory::Entered {
+        memory::enter(&self.memory)
+    }
+
+    // A VM that was stopped in the middle of an allocation can't be resumed,
+    // so it doesn't run anything again.
+    pub(crate) fn usable(&self) -> Result<(), WrenError> {
+        match self.memory.limit() {
+            Some(limit) if self.memory.poisoned() => Err(WrenError::OutOfMemory { limit }),
+            _ => Ok(()),
+        }
+    }
+
+    fn begin(&mut self) {
+        self.user_data().errors.clear();
+        // Garbage left behind by a run that hit the limit shouldn't count
+        // against the next one.
+        if self.memory.over_limit() {
+            unsafe { wrenCollectGarbage(self.inner) };
+        }
+        self.memory.begin();
+    }
+
+    fn check(&mut self, res: WrenInterpretResult) -> Result<(), WrenError> {
+        let result = self.user_data().errors.finish(res);
+        match self.memory.limit() {
+            Some(limit) if self.memory.exceeded() => Err(WrenError::OutOfMemory { limit }),
+            _ => result,
+        }
+    }
+
+    fn user_data(&mut self) -> &mut UserData {
+        unsafe { &mut *(wrenGetUserData(self.inner) as *mut UserData) }
+    }
+}
+
+// Quotes `text` as a Wren string literal.
+// Counts parameters the way `wrenMakeCallHandle` does, so underscores in
+// method names don't count.
+fn arity(signature: &str) -> usize {
+    let params = |part: &str| part.matches('_').count();
+    let mut arity = 0;
+    if signature.ends_with(')') {
+        arity += signature.rfind('(').map_or(0, |open| params(&signature[open..]));
+    }
+    if signature.starts_with('[') {
+        arity += signature.find(']').map_or(0, |close| params(&signature[..close]));
+    }
+    arity
+}
+
+fn string_literal(text: &str) -> String {
+    let mut literal = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' | '\\' | '%' => {
+                literal.push('\\');
+                literal.push(c);
+            }
+            '\n' => literal.push_str("\\n"),
+            c => literal.push(c),
+        }
+    }
+    literal.push('"');
+    literal
 }
 
 impl Drop for Wren {
+    // A VM that ran out of memory is freed like any other. Wren may collect
+    // garbage whenever it allocates, so its objects were consistent when it
+    // was stopped, and freeing them doesn't run any Wren code.
     fn drop(&mut self) {
+        #[cfg(feature = "serde")]
+        drop(self.maps.take());
         unsafe {
             let user_data = wrenGetUserData(self.inner) as *mut UserData;
-            Box::from_raw(user_data);
+            // Finalizers may drop handles while the VM is freed, and find
+            // them already released.
+            let handles = (*user_data).handles.drain().collect::<Vec<_>>();
+            for raw in handles {
+                wrenReleaseHandle(self.inner, raw);
+            }
             wrenFreeVM(self.inner);
+            self.alive.set(false);
+            drop(Box::from_raw(user_data));
         }
     }
 }
@@ -0,0 +1,129 @@
+#[macro_use]
+extern crate thrush;
+
+use thrush::foreign::{ForeignMethod, WrenClass};
+use thrush::output::CapturedOutput;
+use thrush::vm::*;
+
+#[derive(Default, WrenClass)]
+#[wren(default)]
+struct Pile(Vec<f64>);
+
+#[thrush::methods]
+impl Pile {
+    fn push(&mut self, n: f64) {
+        self.0.push(n);
+    }
+
+    fn absorb(&mut self, other: &mut Pile) {
+        self.0.append(&mut other.0);
+    }
+
+    #[wren(getter)]
+    fn count(&self) -> f64 {
+        self.0.len() as f64
+    }
+
+    fn matches(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+const APPEND: ForeignMethod = wren_fn!(slots, (pile: [Pile], other: &[Pile]) {
+    pile.0.extend(other.0.iter().cloned());
+});
+
+const SAME_SIZE: ForeignMethod = wren_fn!(slots, (pile: &[Pile], other: &[Pile]) -> bool {
+    pile.0.len() == other.0.len()
+});
+
+// Overwriting the receiver's slot leaves the borrow as the only thing
+// holding on to it, while the strings make Wren collect garbage.
+const REFILL: ForeignMethod = wren_fn!(slots, (pile: [Pile]) {
+    for i in 0..10000 {
+        slots.set(0, format!("garbage {}", i)).unwrap();
+    }
+    pile.push(1.0);
+});
+
+const SCRIPT: &'static str = r##"
+foreign class Pile {
+    construct new() {}
+    foreign push(n)
+    foreign absorb(other)
+    foreign count
+    foreign matches(other)
+    foreign append(other)
+    foreign sameSize(other)
+}
+
+var a = Pile.new()
+a.push(1)
+var b = Pile.new()
+b.push(2)
+"##;
+
+fn vm(output: &CapturedOutput) -> Wren {
+    let mut vm = WrenBuilder::new()
+        .write_fn(output.writer())
+        .bind::<Pile>()
+        .bind_method("main", "Pile", false, "append", APPEND)
+        .bind_method("main", "Pile", false, "sameSize", SAME_SIZE)
+        .build();
+    vm.interpret(SCRIPT).unwrap();
+    vm
+}
+
+#[test]
+fn distinct_objects() {
+    let output = CapturedOutput::new();
+    let mut vm = vm(&output);
+    vm.interpret(
+        r##"
+System.print(a.sameSize(a))
+System.print([a.matches(a), a.matches(b)])
+a.append(b)
+a.absorb(b)
+System.print([a.count, b.count])
+"##,
+    ).unwrap();
+    assert_eq!(output.contents(), "true\n[true, false]\n[3, 0]\n");
+}
+
+#[test]
+fn conflicting_borrows() {
+    let output = CapturedOutput::new();
+    let mut vm = vm(&output);
+    let error = |vm: &mut Wren, source: &str| vm.interpret(source).unwrap_err().to_string();
+
+    assert!(error(&mut vm, "a.append(a)").starts_with("Pile is already mutably borrowed"));
+    assert!(error(&mut vm, "a.absorb(a)").starts_with("Pile is already borrowed"));
+
+    // The failed calls released their borrows.
+    vm.interpret("a.absorb(b)\nSystem.print(a.count)").unwrap();
+    assert_eq!(output.contents(), "2\n");
+}
+
+#[test]
+fn borrows_keep_objects_alive() {
+    let output = CapturedOutput::new();
+    let mut vm = WrenBuilder::new()
+        .write_fn(output.writer())
+        .initial_heap_size(64 * 1024)
+        .min_heap_size(64 * 1024)
+        .bind::<Pile>()
+        .bind_method("main", "Pile", false, "refill", REFILL)
+        .build();
+    vm.interpret(
+        r##"
+foreign class Pile {
+    construct new() {}
+    foreign refill()
+}
+
+Pile.new().refill()
+System.print("done")
+"##,
+    ).unwrap();
+    assert_eq!(output.contents(), "done\n");
+}
@@ -0,0 +1,111 @@
+extern crate thrush;
+
+use thrush::errors::WrenError;
+use thrush::vm::*;
+
+#[test]
+fn call_methods() {
+    let mut vm = WrenBuilder::new().build();
+    vm.interpret("var x = 1").unwrap();
+
+    let count = vm.make_call("count").unwrap();
+    let n: f64 = vm.call("hello", &count, ()).unwrap();
+    assert_eq!(n, 5.0);
+
+    let plus = vm.make_call("+(_)").unwrap();
+    let s: String = vm.call("foo", &plus, ("bar",)).unwrap();
+    assert_eq!(s, "foobar");
+
+    let n: f64 = vm.call(1.5, &plus, (2.0,)).unwrap();
+    assert_eq!(n, 3.5);
+}
+
+#[test]
+fn call_errors() {
+    let mut vm = WrenBuilder::new().build();
+    vm.interpret("var x = 1").unwrap();
+
+    let plus = vm.make_call("+(_)").unwrap();
+    match vm.call::<_, _, f64>(1.0, &plus, ()) {
+        Err(WrenError::Arity { expected: 1, got: 0, .. }) => {}
+        other => panic!("expected arity error, got {:?}", other),
+    }
+    match vm.call::<_, _, f64>(1.0, &plus, ("nope",)) {
+        Err(WrenError::Runtime { .. }) => {}
+        other => panic!("expected runtime error, got {:?}", other),
+    }
+    match vm.call::<_, _, bool>(1.0, &plus, (1.0,)) {
+        Err(WrenError::Conversion(_)) => {}
+        other => panic!("expected conversion error, got {:?}", other),
+    }
+    for signature in &["", "bad\0sig"] {
+        match vm.make_call(signature) {
+            Err(WrenError::InvalidSignature(_)) => {}
+            other => panic!("expected invalid signature, got {:?}", other.err()),
+        }
+    }
+
+    let mut other = WrenBuilder::new().build();
+    match other.call::<_, _, f64>(1.0, &plus, (1.0,)) {
+        Err(WrenError::InvalidHandle) => {}
+        other => panic!("expected invalid handle, got {:?}", other),
+    }
+}
+
+#[test]
+fn static_methods_and_variables() {
+    let mut vm = WrenBuilder::new().build();
+    vm.interpret(
+        r##"
+var MaxPlayers = 8
+var Title = "thrush"
+
+class Game {
+    static update(dt) { dt * 2 }
+}
+"##,
+    ).unwrap();
+
+    let players: f64 = vm.get_variable("main", "MaxPlayers").unwrap();
+    assert_eq!(players, 8.0);
+    let title: String = vm.get_variable("main", "Title").unwrap();
+    assert_eq!(title, "thrush");
+
+    match vm.get_variable::<f64>("main", "Title") {
+        Err(WrenError::Conversion(_)) => {}
+        other => panic!("expected conversion error, got {:?}", other),
+    }
+    match vm.get_variable::<f64>("main", "MinPlayers") {
+        Err(WrenError::UnknownVariable { .. }) => {}
+        other => panic!("expected unknown variable, got {:?}", other),
+    }
+    match vm.get_variable::<f64>("nowhere", "MaxPlayers") {
+        Err(WrenError::UnknownVariable { .. }) => {}
+        other => panic!("expected unknown variable, got {:?}", other),
+    }
+    assert!(vm.get_class("main", "MaxPlayers").is_err());
+
+    let game = vm.get_class("main", "Game").unwrap();
+    let update = vm.make_call("update(_)").unwrap();
+    let n: f64 = vm.call(&game, &update, (0.5,)).unwrap();
+    assert_eq!(n, 1.0);
+}
+
+#[test]
+fn underscored_names() {
+    let mut vm = WrenBuilder::new().build();
+    vm.interpret(
+        r##"
+class Task {
+    static is_ready { true }
+    static do_thing(n) { n + 1 }
+}
+"##,
+    ).unwrap();
+    let task = vm.get_class("main", "Task").unwrap();
+
+    let is_ready = vm.make_call("is_ready").unwrap();
+    assert!(vm.call::<_, _, bool>(&task, &is_ready, ()).unwrap());
+    let do_thing = vm.make_call("do_thing(_)").unwrap();
+    assert_eq!(vm.call::<_, _, f64>(&task, &do_thing, (1.0,)).unwrap(), 2.0);
+}
@@ -0,0 +1,90 @@
+extern crate thrush;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use thrush::errors::WrenError;
+use thrush::output::CapturedOutput;
+use thrush::vm::*;
+
+const SCRIPT: &'static str = r##"
+class Assets {
+    foreign static load(name)
+    foreign static loaded
+}
+
+System.print(Assets.load("grass.png"))
+System.print(Assets.load("tree.png"))
+System.print(Assets.load("grass.png"))
+System.print(Assets.loaded)
+"##;
+
+#[test]
+fn closures_capture_state() {
+    let cache: Rc<RefCell<HashMap<String, f64>>> = Default::default();
+    let loads = cache.clone();
+    let loaded = cache.clone();
+
+    let output = CapturedOutput::new();
+    let mut vm = WrenBuilder::new()
+        .write_fn(output.writer())
+        .bind_closure("main", "Assets", true, "load", "(_)", move |slots| {
+            let name: String = slots.get(1)?;
+            let mut cache = loads.borrow_mut();
+            let next = cache.len() as f64;
+            let id = *cache.entry(name).or_insert(next);
+            slots.set(0, id)
+        })
+        .unwrap()
+        .bind_closure("main", "Assets", true, "loaded", "", move |slots| {
+            slots.set(0, loaded.borrow().len() as f64)
+        })
+        .unwrap()
+        .build();
+    vm.interpret(SCRIPT).unwrap();
+
+    assert_eq!(output.contents(), "0\n1\n0\n2\n");
+    assert_eq!(cache.borrow().len(), 2);
+}
+
+#[test]
+fn closure_errors_and_rebinding() {
+    let mut vm = WrenBuilder::new()
+        .bind_closure("main", "Host", true, "check", "(_)", |_| Err("first".into()))
+        .unwrap()
+        .bind_closure("main", "Host", true, "check", "(_)", |slots| {
+            let n: f64 = slots.get(1)?;
+            if n < 0.0 {
+                return Err(format!("{} is negative", n));
+            }
+            slots.set(0, n)
+        })
+        .unwrap()
+        .build();
+    vm.interpret("class Host {\n  foreign static check(n)\n}")
+        .unwrap();
+    vm.interpret("Host.check(1)").unwrap();
+    let error = vm.interpret("Host.check(-1)").unwrap_err();
+    assert!(error.to_string().starts_with("-1 is negative"));
+}
+
+#[test]
+fn closure_limit() {
+    let mut builder = WrenBuilder::new();
+    for i in 0..128 {
+        let name = format!("method{}", i);
+        builder = builder
+            .bind_closure("main", "Host", true, &name, "()", |_| Ok(()))
+            .unwrap();
+    }
+    // Rebinding doesn't take another slot.
+    builder = builder
+        .bind_closure("main", "Host", true, "method0", "()", |_| Ok(()))
+        .unwrap();
+    match builder.bind_closure("main", "Host", true, "extra", "()", |_| Ok(())) {
+        Err(WrenError::TooManyClosures { limit: 128 }) => {}
+        Err(err) => panic!("unexpected error {}", err),
+        Ok(_) => panic!("the 129th closure was bound"),
+    }
+}
@@ -0,0 +1,124 @@
+extern crate thrush;
+
+use thrush::errors::WrenError;
+use thrush::foreign::{Slots, WrenClass, WrenConstruct};
+use thrush::output::CapturedOutput;
+use thrush::vm::*;
+
+#[derive(Default, WrenClass)]
+#[wren(construct = "new(_,_)")]
+struct Buffer {
+    name: String,
+    data: Vec<f64>,
+}
+
+impl WrenConstruct for Buffer {
+    fn construct(args: &mut Slots) -> Result<Self, String> {
+        let name = args.get(1)?;
+        let capacity: f64 = args.get(2)?;
+        if capacity < 0.0 {
+            return Err(format!("negative capacity {}", capacity));
+        }
+        Ok(Buffer {
+            name,
+            data: vec![0.0; capacity as usize],
+        })
+    }
+}
+
+#[thrush::methods]
+impl Buffer {
+    #[wren(getter)]
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    #[wren(getter)]
+    fn capacity(&self) -> f64 {
+        self.data.len() as f64
+    }
+}
+
+const CLASSES: &'static str = r##"
+foreign class Buffer {
+    construct new(name, capacity) {}
+    foreign name
+    foreign capacity
+}
+"##;
+
+#[test]
+fn constructor_arguments() {
+    let output = CapturedOutput::new();
+    let mut vm = WrenBuilder::new()
+        .write_fn(output.writer())
+        .bind::<Buffer>()
+        .build();
+    vm.interpret(CLASSES).unwrap();
+    vm.interpret(
+        r##"
+var buffer = Buffer.new("scratch", 16)
+System.print(buffer.name)
+System.print(buffer.capacity)
+"##,
+    ).unwrap();
+    assert_eq!(output.contents(), "scratch\n16\n");
+}
+
+#[test]
+fn constructor_errors() {
+    let mut vm = WrenBuilder::new().bind::<Buffer>().build();
+    vm.interpret(CLASSES).unwrap();
+
+    match vm.interpret("Buffer.new(\"scratch\", -1)") {
+        Err(WrenError::Runtime { message, panicked, .. }) => {
+            assert_eq!(message, "negative capacity -1");
+            assert!(!panicked);
+        }
+        other => panic!("unexpected result {:?}", other),
+    }
+    assert!(vm.interpret("Buffer.new(1, 2)").is_err());
+
+    // The failed objects can't be used, but the VM still can.
+    vm.interpret("var buffer = Buffer.new(\"ok\", 1)").unwrap();
+}
+
+#[derive(WrenClass)]
+#[wren(construct = "new(_)")]
+struct Scribble(String);
+
+impl WrenConstruct for Scribble {
+    fn construct(args: &mut Slots) -> Result<Self, String> {
+        let text = args.get(1)?;
+        args.set(0, "not a class")?;
+        Ok(Scribble(text))
+    }
+}
+
+#[thrush::methods]
+impl Scribble {
+    #[wren(getter)]
+    fn text(&self) -> String {
+        self.0.clone()
+    }
+}
+
+#[test]
+fn constructors_can_overwrite_slot_zero() {
+    let output = CapturedOutput::new();
+    let mut vm = WrenBuilder::new()
+        .write_fn(output.writer())
+        .bind::<Scribble>()
+        .build();
+    vm.interpret(
+        r##"
+foreign class Scribble {
+    construct new(text) {}
+    foreign text
+}
+
+System.print(Scribble.new("hello").text)
+"##,
+    ).unwrap();
+    assert_eq!(output.contents(), "hello\n");
+}
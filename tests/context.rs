@@ -0,0 +1,74 @@
+extern crate thrush;
+
+use thrush::foreign::ForeignMethod;
+use thrush::vm::*;
+
+struct Game {
+    score: f64,
+}
+
+const SCRIPT: &'static str = r##"
+class Host {
+    foreign static score(points)
+    foreign static wrongType()
+}
+"##;
+
+fn score() -> ForeignMethod {
+    ForeignMethod::new("(_)", |slots| {
+        let points: f64 = slots.get(1)?;
+        let game = slots.context_mut::<Game>()?;
+        game.score += points;
+        let total = game.score;
+        slots.set(0, total)
+    })
+}
+
+fn wrong_type() -> ForeignMethod {
+    ForeignMethod::new("()", |slots| {
+        let name = slots.context::<String>()?.clone();
+        slots.set(0, name)
+    })
+}
+
+#[test]
+fn context_in_foreign_methods() {
+    let mut vm = WrenBuilder::new()
+        .with_context(Game { score: 0.0 })
+        .bind_method("main", "Host", true, "score", score())
+        .bind_method("main", "Host", true, "wrongType", wrong_type())
+        .build();
+    vm.interpret(SCRIPT).unwrap();
+    vm.interpret("Host.score(10)\nHost.score(5)").unwrap();
+    assert_eq!(vm.context::<Game>().unwrap().score, 15.0);
+    assert!(vm.context::<String>().is_none());
+
+    let error = vm.interpret("Host.wrongType()").unwrap_err();
+    assert!(error.to_string().starts_with("the VM context has a different type"));
+}
+
+#[test]
+fn missing_context() {
+    let mut vm = WrenBuilder::new()
+        .bind_method("main", "Host", true, "score", score())
+        .build();
+    vm.interpret(SCRIPT).unwrap();
+    let error = vm.interpret("Host.score(1)").unwrap_err();
+    assert!(error.to_string().starts_with("the VM has no context"));
+}
+
+#[test]
+fn context_in_closures() {
+    let mut vm = WrenBuilder::new()
+        .with_context(Game { score: 0.0 })
+        .bind_closure("main", "Host", true, "score", "(_)", |slots| {
+            let points: f64 = slots.get(1)?;
+            slots.context_mut::<Game>()?.score += points;
+            Ok(())
+        })
+        .unwrap()
+        .build();
+    vm.interpret(SCRIPT).unwrap();
+    vm.interpret("Host.score(3)\nHost.score(4)").unwrap();
+    assert_eq!(vm.context::<Game>().unwrap().score, 7.0);
+}
@@ -0,0 +1,110 @@
+#[macro_use]
+extern crate thrush;
+
+use thrush::foreign::{Bytes, ForeignMethod, FromSlot, IntoSlot, Slots};
+use thrush::output::CapturedOutput;
+use thrush::vm::*;
+
+struct Celsius(f64);
+
+impl FromSlot for Celsius {
+    fn from_slot(slots: &mut Slots, slot: usize) -> Result<Celsius, String> {
+        let degrees: f64 = slots.get(slot)?;
+        if degrees < -273.15 {
+            return Err(format!("{} is below absolute zero", degrees));
+        }
+        Ok(Celsius(degrees))
+    }
+}
+
+impl IntoSlot for Celsius {
+    fn into_slot(self, slots: &mut Slots, slot: usize) -> Result<(), String> {
+        slots.set(slot, self.0)
+    }
+}
+
+const HALF: ForeignMethod = wren_fn!(vm, (_, n: u8) -> Option<u8> {
+    if n.is_multiple_of(2) { Some(n / 2) } else { None }
+});
+
+const REPEAT: ForeignMethod = wren_fn!(vm, (_, c: char, times: Option<usize>) -> String {
+    ::std::iter::repeat_n(c, times.unwrap_or(1)).collect::<String>()
+});
+
+const LENGTH: ForeignMethod = wren_fn!(vm, (_, bytes: Bytes) -> i64 {
+    bytes.0.len() as i64
+});
+
+const SHOUT: ForeignMethod = wren_fn!(vm, (_, text: &str, mark: &str) -> String {
+    text.to_uppercase() + mark
+});
+
+const DOUBLE: ForeignMethod = wren_fn!(vm, (_, n: i64) -> i64 {
+    n * 2
+});
+
+const WARMER: ForeignMethod = wren_fn!(vm, (_, t: Celsius) -> Celsius {
+    Celsius(t.0 + 10.0)
+});
+
+const SCRIPT: &'static str = r##"
+class Util {
+    foreign static half(n)
+    foreign static repeat(c, times)
+    foreign static length(bytes)
+    foreign static double(n)
+    foreign static shout(text, mark)
+    foreign static warmer(t)
+}
+"##;
+
+fn vm(output: &CapturedOutput) -> Wren {
+    let mut vm = WrenBuilder::new()
+        .write_fn(output.writer())
+        .bind_method("main", "Util", true, "half", HALF)
+        .bind_method("main", "Util", true, "repeat", REPEAT)
+        .bind_method("main", "Util", true, "length", LENGTH)
+        .bind_method("main", "Util", true, "double", DOUBLE)
+        .bind_method("main", "Util", true, "shout", SHOUT)
+        .bind_method("main", "Util", true, "warmer", WARMER)
+        .build();
+    vm.interpret(SCRIPT).unwrap();
+    vm
+}
+
+#[test]
+fn converts_arguments_and_results() {
+    let output = CapturedOutput::new();
+    let mut vm = vm(&output);
+    vm.interpret(
+        r##"
+System.print(Util.half(200))
+System.print(Util.half(3))
+System.print(Util.repeat("x", 3))
+System.print(Util.repeat("y", null))
+System.print(Util.length("héllo"))
+System.print(Util.warmer(-5))
+System.print(Util.double(4503599627370496) == 9007199254740992)
+System.print(Util.shout("hey", "!"))
+"##,
+    ).unwrap();
+    assert_eq!(output.contents(), "100\nnull\nxxx\ny\n6\n5\ntrue\nHEY!\n");
+}
+
+#[test]
+fn rejects_bad_arguments() {
+    let output = CapturedOutput::new();
+    let mut vm = vm(&output);
+    let error = |vm: &mut Wren, source: &str| vm.interpret(source).unwrap_err().to_string();
+
+    assert!(error(&mut vm, "Util.half(256)").starts_with("256 is out of range for u8"));
+    assert!(error(&mut vm, "Util.half(-1)").starts_with("-1 is out of range for u8"));
+    assert!(error(&mut vm, "Util.half(1.5)").starts_with("1.5 is out of range for u8"));
+    assert!(error(&mut vm, "Util.double(9007199254740994)")
+        .starts_with("9007199254740994 is out of range for i64"));
+    assert!(error(&mut vm, "Util.double(4503599627370497)")
+        .starts_with("9007199254740994 is out of range for a Wren number"));
+    assert!(error(&mut vm, "Util.repeat(\"xy\", 1)").contains("single character"));
+    assert!(error(&mut vm, "Util.shout(1, \"!\")").starts_with("expecting String for slot 1"));
+    assert!(error(&mut vm, "Util.warmer(-300)").starts_with("-300 is below absolute zero"));
+}
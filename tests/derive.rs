@@ -0,0 +1,57 @@
+#[macro_use]
+extern crate thrush;
+
+use thrush::errors::WrenError;
+use thrush::foreign::{ForeignMethod, WrenClass};
+use thrush::vm::*;
+
+#[derive(Default, WrenClass)]
+#[wren(default)]
+struct Apple;
+
+#[derive(Default, WrenClass)]
+#[wren(module = "main", class = "Pear", default)]
+struct NotAnApple;
+
+const BITE: ForeignMethod = wren_fn!(vm, (
+    _apple: [Apple]
+) -> String {
+    "crunch".to_string()
+});
+
+#[test]
+fn derived_names() {
+    assert_eq!(Apple::MODULE, "main");
+    assert_eq!(Apple::CLASS, "Apple");
+    assert_eq!(NotAnApple::CLASS, "Pear");
+}
+
+#[test]
+fn foreign_types_are_not_confused() {
+    let mut vm = WrenBuilder::new()
+        .bind_class::<Apple>()
+        .bind_class::<NotAnApple>()
+        .bind_method("main", "Apple", false, "bite", BITE)
+        .bind_method("main", "Pear", false, "bite", BITE)
+        .build();
+    vm.interpret(
+        r##"
+foreign class Apple {
+    construct new() {}
+    foreign bite()
+}
+
+foreign class Pear {
+    construct new() {}
+    foreign bite()
+}
+
+Apple.new().bite()
+"##,
+    ).unwrap();
+
+    match vm.interpret("Pear.new().bite()") {
+        Err(WrenError::Runtime { message, .. }) => assert!(message.contains("invalid type id")),
+        other => panic!("expected a type error, got {:?}", other),
+    }
+}
@@ -0,0 +1,114 @@
+extern crate thrush;
+
+use std::error::Error;
+
+use thrush::errors::{Diagnostic, Trace, WrenError};
+use thrush::vm::*;
+
+#[test]
+fn display_compile_error() {
+    let err = WrenError::Compile {
+        errors: vec![
+            Diagnostic {
+                module: "main".into(),
+                line: 3,
+                message: "Error at 'foo': Expect expression.".into(),
+            },
+            Diagnostic {
+                module: "main".into(),
+                line: 5,
+                message: "Error at ')': Expect expression.".into(),
+            },
+        ],
+        stray: vec![],
+    };
+    assert_eq!(
+        err.to_string(),
+        "main:3: Error at 'foo': Expect expression.\nmain:5: Error at ')': Expect expression."
+    );
+}
+
+#[test]
+fn display_runtime_error() {
+    let err = WrenError::Runtime {
+        message: "Null does not implement 'bar()'.".into(),
+        stack: vec![
+            Trace {
+                function: "foo()".into(),
+                module: "util".into(),
+                line: 12,
+            },
+            Trace {
+                function: "(script)".into(),
+                module: "main".into(),
+                line: 2,
+            },
+        ],
+        stray: vec![],
+        panicked: false,
+    };
+    assert_eq!(
+        err.to_string(),
+        "Null does not implement 'bar()'.\n    at foo() (util:12)\n    at (script) (main:2)"
+    );
+}
+
+fn run(source: &str) -> Result<(), Box<dyn Error>> {
+    let mut vm = WrenBuilder::new().build();
+    vm.interpret(source)?;
+    Ok(())
+}
+
+#[test]
+fn boxed_errors() {
+    let err = run("Fiber.abort(\"oops\")").unwrap_err();
+    assert!(err.to_string().starts_with("oops"));
+}
+
+#[test]
+fn collects_every_compile_error() {
+    let mut vm = WrenBuilder::new().build();
+    let err = vm.interpret("var a = )\nvar b = 1\nvar c = )\n").unwrap_err();
+    match err {
+        WrenError::Compile { errors, .. } => {
+            let lines: Vec<u32> = errors.iter().map(|e| e.line).collect();
+            assert!(lines.contains(&1));
+            assert!(lines.contains(&3));
+            assert!(errors.iter().all(|e| e.module == "main"));
+        }
+        other => panic!("expected compile errors, got {:?}", other),
+    }
+}
+
+#[test]
+fn runtime_error_stack() {
+    let mut vm = WrenBuilder::new().build();
+    let err = vm.interpret("class Foo {\n  static bar() { Fiber.abort(\"nope\") }\n}\nFoo.bar()\n")
+        .unwrap_err();
+    match err {
+        WrenError::Runtime {
+            message,
+            stack,
+            stray,
+            panicked,
+        } => {
+            assert_eq!(message, "nope");
+            assert!(!panicked);
+            assert_eq!(stack.len(), 2);
+            assert_eq!(stack[0].line, 2);
+            assert_eq!(stack[1].line, 4);
+            assert!(stray.is_empty());
+        }
+        other => panic!("expected runtime error, got {:?}", other),
+    }
+
+    // a failed run doesn't leak into the next one
+    vm.interpret("var ok = true").unwrap();
+    match vm.interpret("var x = )").unwrap_err() {
+        WrenError::Compile { errors, stray } => {
+            assert!(!errors.is_empty());
+            assert!(stray.is_empty());
+        }
+        other => panic!("expected compile error, got {:?}", other),
+    }
+}
@@ -6,21 +6,15 @@ use std::collections::HashMap;
 use thrush::vm::*;
 use thrush::foreign::{ForeignMethod, WrenClass};
 
-#[derive(Default, Clone)]
+#[derive(Default, Clone, WrenClass)]
+#[wren(module = "main", class = "RustMap", default)]
 struct MyMap(HashMap<String, String>);
 
-impl WrenClass for MyMap {
-    const ID: usize = 0;
-    const MODULE: &'static str = "main";
-    const CLASS: &'static str = "RustMap";
-}
-
 const INSERT: ForeignMethod = wren_fn!(vm, (
     map: [MyMap],
     key: String,
     value: String
 ) {
-    let map = unsafe { &mut *map };
     map.0.insert(key, value);
 });
 
@@ -28,7 +22,6 @@ const INSERT: ForeignMethod = wren_fn!(vm, (
 const PRINT: ForeignMethod = wren_fn!(vm, (
     map: [MyMap]
 ) {
-    let map = unsafe { &*map };
     for (key, value) in map.0.iter() {
         println!("{}: {}", key, value);
     }
@@ -39,7 +32,6 @@ use std::fmt::Write;
 const COMBINE: ForeignMethod = wren_fn!(vm, (
     map: [MyMap]
 ) -> String {
-    let map = unsafe { &*map };
     let mut out = String::new();
     for (key, value) in map.0.iter() {
         write!(out, "{}: {}\n", key, value);
@@ -51,7 +43,6 @@ const GET: ForeignMethod = wren_fn!(vm, Brackets, (
     map: [MyMap],
     key: String
 ) -> String {
-    let map = unsafe { &*map };
     map.0.get(&key).map(Clone::clone).unwrap_or_default()
 });
 
@@ -59,14 +50,12 @@ const CONTAINS: ForeignMethod = wren_fn!(vm, (
     map: [MyMap],
     key: String
 ) -> bool {
-    let map = unsafe { &*map };
     map.0.contains_key(&key)
 });
 
 const COPY: ForeignMethod = wren_fn!(vm, (
     map: [MyMap]
 ) -> [MyMap] {
-    let map = unsafe { &*map };
     map.clone()
 });
 
@@ -76,15 +65,9 @@ const UHOH: ForeignMethod = wren_fn!(vm, (
     LolWut
 });
 
-#[derive(Default)]
+#[derive(Default, WrenClass)]
 struct LolWut;
 
-impl WrenClass for LolWut {
-    const ID: usize = 3;
-    const MODULE: &'static str = "main";
-    const CLASS: &'static str = "LolWut";
-}
-
 const SCRIPT: &'static str = r##"
 foreign class RustMap {
     construct new() {}
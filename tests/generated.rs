@@ -0,0 +1,87 @@
+extern crate thrush;
+
+use std::collections::HashMap;
+
+use thrush::foreign::WrenClass;
+use thrush::output::CapturedOutput;
+use thrush::vm::*;
+
+#[derive(Default, WrenClass)]
+#[wren(module = "rust/collections", class = "RustMap", default)]
+struct MyMap(HashMap<String, String>);
+
+#[thrush::methods]
+impl MyMap {
+    fn insert(&mut self, key: String, value: String) {
+        self.0.insert(key, value);
+    }
+
+    #[wren(subscript)]
+    fn get(&self, key: String) -> String {
+        self.0.get(&key).cloned().unwrap_or_default()
+    }
+
+    #[wren(getter)]
+    fn count(&self) -> f64 {
+        self.0.len() as f64
+    }
+
+    fn describe(name: String) -> String {
+        format!("RustMap({})", name)
+    }
+}
+
+#[derive(Default, WrenClass)]
+#[wren(module = "rust/collections", construct = "new()", construct = "of(_,_)", default)]
+struct Pair;
+
+const EXPECTED: &'static str = r##"foreign class Pair {
+  construct new() {}
+  construct of(arg0, arg1) {}
+}
+
+foreign class RustMap {
+  construct new() {}
+  foreign static describe(arg0)
+  foreign [arg0]
+  foreign count
+  foreign insert(arg0, arg1)
+}
+
+"##;
+
+#[test]
+fn renders_declarations() {
+    let mut vm = WrenBuilder::new()
+        .bind::<MyMap>()
+        .bind_class::<Pair>()
+        .build();
+    assert_eq!(vm.foreign_source("rust/collections").unwrap(), EXPECTED);
+    assert_eq!(vm.foreign_source("main"), None);
+}
+
+#[test]
+fn imports_generated_module() {
+    let output = CapturedOutput::new();
+    let mut vm = WrenBuilder::new()
+        .write_fn(output.writer())
+        .bind::<MyMap>()
+        .module_loader(|name: &str| match name {
+            "rust/collections" => Some("var Empty = RustMap.new()\n".to_string()),
+            _ => None,
+        })
+        .build();
+    vm.interpret(
+        r##"
+import "rust/collections" for RustMap, Empty
+
+var map = RustMap.new()
+map.insert("Hello", "World")
+System.print(map["Hello"])
+System.print(map.count)
+System.print(Empty.count)
+System.print(RustMap.describe("map"))
+"##,
+    ).unwrap();
+    assert_eq!(output.contents(), "World\n1\n0\nRustMap(map)\n");
+}
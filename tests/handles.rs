@@ -0,0 +1,71 @@
+#[macro_use]
+extern crate thrush;
+
+use thrush::vm::*;
+use thrush::foreign::{DefaultConstruct, ForeignMethod, WrenClass};
+
+#[derive(Default)]
+struct Callbacks(Vec<Handle>);
+
+impl WrenClass for Callbacks {
+    const MODULE: &'static str = "main";
+    const CLASS: &'static str = "Callbacks";
+}
+
+impl DefaultConstruct for Callbacks {}
+
+const ADD: ForeignMethod = wren_fn!(vm, (
+    callbacks: [Callbacks],
+    callback: Handle
+) {
+    callbacks.0.push(callback);
+});
+
+const GET: ForeignMethod = wren_fn!(vm, Brackets, (
+    callbacks: [Callbacks],
+    index: f64
+) -> Handle {
+    callbacks.0[index as usize].clone()
+});
+
+const SCRIPT: &'static str = r##"
+foreign class Callbacks {
+    construct new() {}
+    foreign add(callback)
+    foreign [index]
+}
+
+var callbacks = Callbacks.new()
+callbacks.add(Fn.new {|x| x * 2 })
+callbacks.add("not a function")
+
+if (callbacks[0].call(3) != 6) Fiber.abort("callback was not preserved")
+if (callbacks[1] != "not a function") Fiber.abort("string was not preserved")
+"##;
+
+#[test]
+fn handles_in_foreign_objects() {
+    let mut vm = WrenBuilder::new()
+        .bind_class::<Callbacks>()
+        .bind_method("main", "Callbacks", false, "add", ADD)
+        .bind_method("main", "Callbacks", false, "", GET)
+        .build();
+    vm.interpret(SCRIPT).unwrap();
+}
+
+#[test]
+fn handles_from_calls() {
+    let mut vm = WrenBuilder::new().build();
+    vm.interpret("var x = 1").unwrap();
+
+    let plus = vm.make_call("+(_)").unwrap();
+    let hello: Handle = vm.call("hello", &plus, (" world",)).unwrap();
+    assert!(hello.is_alive());
+
+    let count = vm.make_call("count").unwrap();
+    let n: f64 = vm.call(&hello, &count, ()).unwrap();
+    assert_eq!(n, 11.0);
+
+    drop(vm);
+    assert!(!hello.is_alive());
+}
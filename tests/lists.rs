@@ -0,0 +1,93 @@
+#[macro_use]
+extern crate thrush;
+
+use thrush::foreign::ForeignMethod;
+use thrush::output::CapturedOutput;
+use thrush::vm::*;
+
+const SUM: ForeignMethod = wren_fn!(vm, (_, xs: Vec<f64>) -> f64 {
+    xs.iter().sum()
+});
+
+const WORDS: ForeignMethod = wren_fn!(vm, (_, text: String) -> Vec<String> {
+    text.split_whitespace().map(String::from).collect()
+});
+
+const TRANSPOSE: ForeignMethod = wren_fn!(vm, (_, rows: Vec<Vec<i32>>) -> Vec<Vec<i32>> {
+    let width = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+    (0..width)
+        .map(|i| rows.iter().filter_map(|row| row.get(i).cloned()).collect())
+        .collect()
+});
+
+// Reports how many slots converting `rows` took.
+const SLOTS: ForeignMethod = wren_fn!(vm, (_, _rows: Vec<Vec<u8>>) -> usize {
+    vm.count()
+});
+
+const SCRIPT: &'static str = r##"
+class Lists {
+    foreign static sum(xs)
+    foreign static words(text)
+    foreign static transpose(rows)
+    foreign static slots(rows)
+}
+"##;
+
+fn vm(output: &CapturedOutput) -> Wren {
+    let mut vm = WrenBuilder::new()
+        .write_fn(output.writer())
+        .bind_method("main", "Lists", true, "sum", SUM)
+        .bind_method("main", "Lists", true, "words", WORDS)
+        .bind_method("main", "Lists", true, "transpose", TRANSPOSE)
+        .bind_method("main", "Lists", true, "slots", SLOTS)
+        .build();
+    vm.interpret(SCRIPT).unwrap();
+    vm
+}
+
+#[test]
+fn lists_and_vecs() {
+    let output = CapturedOutput::new();
+    let mut vm = vm(&output);
+    vm.interpret(
+        r##"
+System.print(Lists.sum([1, 2, 3.5]))
+System.print(Lists.sum([]))
+System.print(Lists.words("a list of words"))
+System.print(Lists.transpose([[1, 2, 3], [4, 5, 6]]))
+"##,
+    ).unwrap();
+    assert_eq!(
+        output.contents(),
+        "6.5\n0\n[a, list, of, words]\n[[1, 4], [2, 5], [3, 6]]\n"
+    );
+}
+
+#[test]
+fn nested_lists_share_a_slot_per_level() {
+    let output = CapturedOutput::new();
+    let mut vm = vm(&output);
+    vm.interpret(
+        r##"
+System.print(Lists.slots([]))
+System.print(Lists.slots([[1], [2, 3], [4], [5, 6, 7]]))
+"##,
+    ).unwrap();
+    // The receiver and argument, then one slot for each level of nesting.
+    assert_eq!(output.contents(), "3\n4\n");
+}
+
+#[test]
+fn bad_elements() {
+    let output = CapturedOutput::new();
+    let mut vm = vm(&output);
+    let error = |vm: &mut Wren, source: &str| vm.interpret(source).unwrap_err().to_string();
+
+    assert!(error(&mut vm, "Lists.sum(1)").starts_with("expecting Vec for slot 1"));
+    assert!(error(&mut vm, "Lists.sum([1, \"two\"])").starts_with("list element 1: expecting f64"));
+    assert!(
+        error(&mut vm, "Lists.transpose([[1], [2, 2.5]])")
+            .starts_with("list element 1: list element 1: 2.5 is out of range for i32")
+    );
+}
@@ -0,0 +1,129 @@
+extern crate thrush;
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use thrush::errors::WrenError;
+use thrush::vm::*;
+
+const LIMIT: usize = 4 * 1024 * 1024;
+
+const HOARD: &'static str = r##"
+class Host {
+    foreign static tick()
+}
+
+{
+    var hoard = []
+    for (i in 0...1000000) {
+        hoard.add("item number %(i)")
+        if (i % 1000 == 0) Host.tick()
+    }
+}
+"##;
+
+#[test]
+fn scripts_past_the_limit_are_stopped() {
+    let ticks = Rc::new(Cell::new(0));
+    let counter = ticks.clone();
+    let mut vm = WrenBuilder::new()
+        .memory_limit(LIMIT)
+        .bind_closure("main", "Host", true, "tick", "()", move |_| {
+            counter.set(counter.get() + 1);
+            Ok(())
+        })
+        .unwrap()
+        .build();
+
+    match vm.interpret(HOARD) {
+        Err(WrenError::OutOfMemory { limit }) => assert_eq!(limit, LIMIT),
+        other => panic!("expected OutOfMemory, got {:?}", other),
+    }
+    // The script was stopped well before it got to the end.
+    assert!(ticks.get() > 0 && ticks.get() < 1000);
+    assert!(vm.memory_stats().peak_bytes <= LIMIT);
+
+    // It can't be resumed, so the VM won't run anything else.
+    match vm.interpret("System.print(\"still here\")") {
+        Err(WrenError::OutOfMemory { limit }) => assert_eq!(limit, LIMIT),
+        other => panic!("expected OutOfMemory, got {:?}", other),
+    }
+}
+
+#[test]
+fn scripts_without_foreign_calls_are_stopped() {
+    let mut vm = WrenBuilder::new().memory_limit(LIMIT).build();
+    match vm.interpret("var a = []\nwhile (true) a.add(\"x\")") {
+        Err(WrenError::OutOfMemory { limit }) => assert_eq!(limit, LIMIT),
+        other => panic!("expected OutOfMemory, got {:?}", other),
+    }
+    assert!(vm.memory_stats().peak_bytes <= LIMIT);
+
+    // Looking up a variable would run the VM too.
+    match vm.get_variable::<String>("main", "a") {
+        Err(WrenError::OutOfMemory { limit }) => assert_eq!(limit, LIMIT),
+        other => panic!("expected OutOfMemory, got {:?}", other),
+    }
+}
+
+#[test]
+fn no_limit_by_default() {
+    let mut vm = WrenBuilder::new().build();
+    vm.interpret(
+        r##"
+var hoard = []
+for (i in 0...200000) hoard.add("item number %(i)")
+"##,
+    ).unwrap();
+}
+
+const CHURN: &'static str = r##"
+for (i in 0...100000) {
+    var garbage = "short lived string number %(i)"
+}
+"##;
+
+#[test]
+fn stats_and_collection() {
+    let mut vm = WrenBuilder::new().build();
+    let fresh = vm.memory_stats();
+    assert!(fresh.current_bytes > 0);
+    assert!(fresh.allocations > 0);
+    assert!(fresh.peak_bytes >= fresh.current_bytes);
+
+    vm.interpret("{\n  var hoard = []\n  for (i in 0...10000) hoard.add(\"item %(i)\")\n}")
+        .unwrap();
+    let before = vm.memory_stats();
+    vm.collect_garbage();
+    let after = vm.memory_stats();
+    assert!(after.current_bytes < before.current_bytes);
+    assert!(after.frees > before.frees);
+    assert_eq!(after.peak_bytes, before.peak_bytes);
+}
+
+#[test]
+fn heap_tuning() {
+    let mut default = WrenBuilder::new().build();
+    default.interpret(CHURN).unwrap();
+
+    let mut tuned = WrenBuilder::new()
+        .initial_heap_size(512 * 1024)
+        .min_heap_size(256 * 1024)
+        .heap_growth_percent(20)
+        .build();
+    tuned.interpret(CHURN).unwrap();
+
+    assert!(tuned.memory_stats().peak_bytes < default.memory_stats().peak_bytes);
+}
+
+#[test]
+fn huge_growth_percent() {
+    // Wrapping to a negative percentage would collect on every allocation.
+    let mut vm = WrenBuilder::new()
+        .initial_heap_size(64 * 1024)
+        .heap_growth_percent(u32::MAX)
+        .build();
+    vm.interpret(CHURN).unwrap();
+    let stats = vm.memory_stats();
+    assert!(stats.frees < stats.allocations / 2);
+}
@@ -0,0 +1,116 @@
+extern crate thrush;
+
+use std::collections::HashMap;
+
+use thrush::foreign::{WrenClass, WrenMethods};
+use thrush::output::CapturedOutput;
+use thrush::vm::*;
+
+#[derive(Default, Clone, WrenClass)]
+#[wren(class = "RustMap", default)]
+struct MyMap(HashMap<String, String>);
+
+#[thrush::methods]
+impl MyMap {
+    fn insert(&mut self, key: String, value: String) {
+        self.0.insert(key, value);
+    }
+
+    fn contains(&self, key: &str) -> bool {
+        self.0.contains_key(key)
+    }
+
+    #[wren(subscript)]
+    fn get(&self, key: String) -> String {
+        self.0.get(&key).cloned().unwrap_or_default()
+    }
+
+    #[wren(subscript_setter)]
+    fn set(&mut self, key: String, value: String) {
+        self.0.insert(key, value);
+    }
+
+    #[wren(getter)]
+    fn count(&self) -> f64 {
+        self.0.len() as f64
+    }
+
+    #[wren(name = "clone")]
+    fn copy(&self) -> MyMap {
+        self.clone()
+    }
+
+    fn merge(&mut self, other: &MyMap) {
+        for (key, value) in other.0.iter() {
+            self.0.insert(key.clone(), value.clone());
+        }
+    }
+
+    fn describe(name: String) -> String {
+        format!("RustMap({})", name)
+    }
+
+    #[wren(skip)]
+    #[allow(dead_code)]
+    fn not_exposed(&self) {}
+}
+
+const SCRIPT: &'static str = r##"
+foreign class RustMap {
+    construct new() {}
+    foreign insert(key, value)
+    foreign contains(key)
+    foreign [key]
+    foreign [key]=(value)
+    foreign count
+    foreign clone()
+    foreign merge(other)
+    foreign static describe(name)
+}
+
+var map = RustMap.new()
+map.insert("Hello", "World")
+map["foo"] = "bar"
+
+var copy = map.clone()
+copy.insert("spam", "eggs")
+map.merge(copy)
+
+System.print(map["Hello"])
+System.print(map.contains("spam"))
+System.print(map.count)
+System.print(RustMap.describe("map"))
+"##;
+
+#[test]
+fn bound_signatures() {
+    let mut signatures: Vec<_> = MyMap::methods()
+        .into_iter()
+        .map(|m| (m.is_static, format!("{}{}", m.name, m.method.signature)))
+        .collect();
+    signatures.sort();
+    assert_eq!(
+        signatures,
+        vec![
+            (false, "[_]".to_string()),
+            (false, "[_]=(_)".to_string()),
+            (false, "clone()".to_string()),
+            (false, "contains(_)".to_string()),
+            (false, "count".to_string()),
+            (false, "insert(_,_)".to_string()),
+            (false, "merge(_)".to_string()),
+            (true, "describe(_)".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn bound_methods() {
+    let output = CapturedOutput::new();
+    let mut vm = WrenBuilder::new()
+        .write_fn(output.writer())
+        .bind::<MyMap>()
+        .build();
+    vm.interpret(SCRIPT).unwrap();
+    assert_eq!(output.contents(), "World\ntrue\n3\nRustMap(map)\n");
+}
@@ -0,0 +1,155 @@
+extern crate thrush;
+
+use std::cell::RefCell;
+use std::env;
+use std::fs::{self, File};
+use std::io::Write;
+use std::rc::Rc;
+
+use thrush::errors::WrenError;
+use thrush::module::FileLoader;
+use thrush::vm::*;
+
+#[test]
+fn closure_loader() {
+    let requested = Rc::new(RefCell::new(vec![]));
+    let log = requested.clone();
+    let mut vm = WrenBuilder::new()
+        .module_loader(move |name: &str| {
+            log.borrow_mut().push(name.to_string());
+            match name {
+                "greeting" => Some("var Greeting = \"hello\"".to_string()),
+                _ => None,
+            }
+        })
+        .build();
+
+    // Looking up a variable doesn't import its module.
+    match vm.get_variable::<String>("greeting", "Greeting") {
+        Err(WrenError::UnknownVariable { .. }) => {}
+        other => panic!("expected unknown variable, got {:?}", other),
+    }
+    assert!(requested.borrow().is_empty());
+
+    vm.interpret("import \"greeting\" for Greeting").unwrap();
+    assert!(vm.interpret("import \"missing\" for Nope").is_err());
+    assert_eq!(*requested.borrow(), vec!["greeting", "missing"]);
+    assert_eq!(vm.get_variable::<String>("greeting", "Greeting").unwrap(), "hello");
+}
+
+#[test]
+fn file_loader() {
+    let root = env::temp_dir().join("thrush-file-loader");
+    fs::create_dir_all(root.join("util")).unwrap();
+    File::create(root.join("util").join("math.wren"))
+        .unwrap()
+        .write_all(b"class Math {\n  static double(n) { n * 2 }\n}\n")
+        .unwrap();
+
+    let loader = FileLoader::new().root("does-not-exist").root(&root);
+    assert_eq!(loader.resolve("util/math"), Some(root.join("util/math.wren")));
+    assert_eq!(loader.resolve("../util/math"), None);
+
+    let mut vm = WrenBuilder::new().module_loader(loader).build();
+    vm.interpret("import \"util/math\" for Math\nMath.double(2)")
+        .unwrap();
+
+    let math = vm.modules().get("util/math").unwrap();
+    assert_eq!(math.path(), Some(root.join("util/math.wren").as_path()));
+    assert_eq!(math.line(2), Some((2, "  static double(n) { n * 2 }")));
+}
+
+#[test]
+fn named_modules() {
+    let mut vm = WrenBuilder::new().build();
+    vm.interpret_module("config", "var Width = 640\nvar Height = 480")
+        .unwrap();
+    vm.interpret("import \"config\" for Width\nSystem.print(Width)")
+        .unwrap();
+    assert_eq!(vm.get_variable::<f64>("config", "Height").unwrap(), 480.0);
+    assert_eq!(vm.modules().names(), vec!["config", "main"]);
+
+    match vm.interpret_module("config", "var Width = 800") {
+        Err(WrenError::DuplicateModule(name)) => assert_eq!(name, "config"),
+        other => panic!("expected DuplicateModule, got {:?}", other),
+    }
+}
+
+#[test]
+fn built_in_modules_are_loaded_too() {
+    let mut vm = WrenBuilder::new().build();
+    vm.interpret("import \"meta\" for Meta").unwrap();
+    match vm.interpret_module("meta", "var Meta = 1") {
+        Err(WrenError::DuplicateModule(name)) => assert_eq!(name, "meta"),
+        other => panic!("expected DuplicateModule, got {:?}", other),
+    }
+    assert!(!vm.modules().contains("meta"));
+}
+
+#[test]
+fn main_lines_after_several_interprets() {
+    let mut vm = WrenBuilder::new().build();
+    vm.interpret("var a = 1").unwrap();
+    assert_eq!(vm.modules().get("main").unwrap().line(1), Some((1, "var a = 1")));
+
+    // Line 1 could be from either one now.
+    vm.interpret("var b = 2").unwrap();
+    let main = vm.modules().get("main").unwrap();
+    assert_eq!(main.source(), "var b = 2");
+    assert_eq!(main.line(1), None);
+}
+
+#[test]
+fn caught_import_failures() {
+    let mut vm = WrenBuilder::new()
+        .module_loader(|name: &str| match name {
+            "broken" => Some("var = )".to_string()),
+            _ => None,
+        })
+        .build();
+    match vm.interpret("import \"broken\"") {
+        Err(WrenError::Compile { errors, .. }) => assert_eq!(errors[0].module, "broken"),
+        other => panic!("expected compile error, got {:?}", other),
+    }
+
+    // Once caught, a later error is what the run failed with, whatever it
+    // says.
+    let mut vm = WrenBuilder::new()
+        .module_loader(|name: &str| match name {
+            "broken" => Some("var = )".to_string()),
+            _ => None,
+        })
+        .build();
+    let source = r##"
+Fiber.new { import "broken" }.try()
+Fiber.abort("Could not compile module 'elsewhere'.")
+"##;
+    match vm.interpret(source) {
+        Err(WrenError::Runtime { message, .. }) => {
+            assert_eq!(message, "Could not compile module 'elsewhere'.")
+        }
+        other => panic!("expected runtime error, got {:?}", other),
+    }
+}
+
+#[test]
+fn compile_errors_map_to_module_source() {
+    let mut vm = WrenBuilder::new()
+        .bind_closure("level", "Host", true, "spawn", "(_)", |_| Ok(()))
+        .unwrap()
+        .build();
+    let err = vm.interpret_module("level", "var a = 1\nvar b = )\n")
+        .unwrap_err();
+    let errors = match err {
+        WrenError::Compile { errors, .. } => errors,
+        other => panic!("expected compile error, got {:?}", other),
+    };
+    assert_eq!(errors[0].module, "level");
+
+    // The generated Host declaration comes first, but the line still maps
+    // back to what was passed in.
+    let level = vm.modules().get("level").unwrap();
+    assert!(errors[0].line > 2);
+    assert_eq!(level.line(errors[0].line), Some((2, "var b = )")));
+    assert_eq!(level.line(1), None);
+}
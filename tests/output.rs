@@ -0,0 +1,32 @@
+extern crate thrush;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use thrush::output::CapturedOutput;
+use thrush::vm::*;
+
+#[test]
+fn captured_output() {
+    let output = CapturedOutput::new();
+    let mut vm = WrenBuilder::new().write_fn(output.writer()).build();
+
+    vm.interpret("System.print(\"Hello\")\nSystem.write(1 + 2)")
+        .unwrap();
+    assert_eq!(output.take(), "Hello\n3");
+
+    vm.interpret("System.print([1, 2])").unwrap();
+    assert_eq!(output.contents(), "[1, 2]\n");
+}
+
+#[test]
+fn custom_write_fn() {
+    let lines = Rc::new(RefCell::new(vec![]));
+    let sink = lines.clone();
+    let mut vm = WrenBuilder::new()
+        .write_fn(move |text: &str| sink.borrow_mut().push(text.to_string()))
+        .build();
+
+    vm.interpret("System.print(\"a\")").unwrap();
+    assert_eq!(*lines.borrow(), vec!["a", "\n"]);
+}
@@ -0,0 +1,112 @@
+#[macro_use]
+extern crate thrush;
+
+use thrush::errors::WrenError;
+use thrush::foreign::{DefaultConstruct, ForeignMethod, WrenClass};
+use thrush::vm::*;
+
+#[derive(Default)]
+struct Fragile;
+
+impl WrenClass for Fragile {
+    const MODULE: &'static str = "main";
+    const CLASS: &'static str = "Fragile";
+}
+
+impl DefaultConstruct for Fragile {}
+
+struct Broken;
+
+impl Default for Broken {
+    fn default() -> Self {
+        panic!("cannot build a Broken")
+    }
+}
+
+impl WrenClass for Broken {
+    const MODULE: &'static str = "main";
+    const CLASS: &'static str = "Broken";
+}
+
+impl DefaultConstruct for Broken {}
+
+const GET: ForeignMethod = wren_fn!(vm, (
+    _fragile: [Fragile],
+    key: String
+) -> String {
+    panic!("{}", key)
+});
+
+const CLASSES: &'static str = r##"
+foreign class Fragile {
+    construct new() {}
+    foreign get(key)
+}
+
+foreign class Broken {
+    construct new() {}
+}
+"##;
+
+fn vm() -> Wren {
+    let mut vm = WrenBuilder::new()
+        .bind_class::<Fragile>()
+        .bind_class::<Broken>()
+        .bind_method("main", "Fragile", false, "get", GET)
+        .build();
+    vm.interpret(CLASSES).unwrap();
+    vm
+}
+
+fn expect_panic(result: Result<(), WrenError>, text: &str) {
+    match result {
+        Err(WrenError::Runtime {
+            message, panicked, ..
+        }) => {
+            assert!(panicked);
+            assert!(message.contains(text), "{:?} should contain {:?}", message, text);
+        }
+        other => panic!("expected a runtime error, got {:?}", other),
+    }
+}
+
+#[test]
+fn method_panics_abort_the_fiber() {
+    let mut vm = vm();
+    expect_panic(vm.interpret("Fragile.new().get(\"no such key\")"), "no such key");
+
+    // the VM is still usable afterwards
+    vm.interpret("var ok = Fragile.new()").unwrap();
+}
+
+#[test]
+fn allocator_panics_abort_the_fiber() {
+    let mut vm = vm();
+    expect_panic(vm.interpret("Broken.new()"), "cannot build a Broken");
+}
+
+#[test]
+fn caught_panics_are_not_reported() {
+    let mut vm = vm();
+    vm.interpret(
+        "var fiber = Fiber.new { Fragile.new().get(\"x\") }\n\
+         if (fiber.try() == null) Fiber.abort(\"expected an error\")",
+    ).unwrap();
+    match vm.interpret("Fiber.abort(\"plain\")") {
+        Err(WrenError::Runtime { panicked, .. }) => assert!(!panicked),
+        other => panic!("expected a runtime error, got {:?}", other),
+    }
+
+    // A script error with the same text as the panic is still not a panic.
+    let copied = vm.interpret(
+        "var fiber = Fiber.new { Fragile.new().get(\"x\") }\n\
+         Fiber.abort(fiber.try() + \"\")",
+    );
+    match copied {
+        Err(WrenError::Runtime { message, panicked, .. }) => {
+            assert_eq!(message, "rust panic: x");
+            assert!(!panicked);
+        }
+        other => panic!("expected a runtime error, got {:?}", other),
+    }
+}
@@ -0,0 +1,161 @@
+extern crate thrush;
+
+use thrush::errors::WrenError;
+use thrush::foreign::{Slots, WrenClass, WrenConstruct};
+use thrush::vm::*;
+
+const UTIL: &'static str = r##"class Util {
+  static fail() { Fiber.abort("nope") }
+}
+"##;
+
+// Util.fail() is on line 10, so the gutter has to fit two digits.
+const MAIN: &'static str = "import \"util\" for Util\n\n\n\n\n\n\n\n\nUtil.fail()";
+
+fn vm() -> (Wren, WrenError) {
+    let mut vm = WrenBuilder::new().build();
+    vm.interpret_module("util", UTIL).unwrap();
+    let error = vm.interpret(MAIN).unwrap_err();
+    (vm, error)
+}
+
+fn functions(error: &WrenError) -> (String, String) {
+    match *error {
+        WrenError::Runtime { ref stack, .. } => {
+            assert_eq!(stack.len(), 2);
+            assert_eq!((stack[0].module.as_str(), stack[0].line), ("util", 2));
+            assert_eq!((stack[1].module.as_str(), stack[1].line), ("main", 10));
+            (stack[0].function.clone(), stack[1].function.clone())
+        }
+        ref other => panic!("expected runtime error, got {:?}", other),
+    }
+}
+
+#[test]
+fn plain_runtime_error() {
+    let (vm, error) = vm();
+    let (fail, script) = functions(&error);
+    assert_eq!(
+        vm.report(&error).plain(),
+        format!(
+            r##"error: nope
+  --> util:2 in {}
+   |
+ 2 |   static fail() {{ Fiber.abort("nope") }}
+   |
+  --> main:10 in {}
+   |
+10 | Util.fail()
+   |
+"##,
+            fail, script
+        )
+    );
+}
+
+#[test]
+fn plain_compile_errors() {
+    let mut vm = WrenBuilder::new().build();
+    let error = vm.interpret_module("broken", "var a = 1\nvar b = )\n")
+        .unwrap_err();
+    let message = match error {
+        WrenError::Compile { ref errors, .. } => {
+            assert_eq!((errors[0].module.as_str(), errors[0].line), ("broken", 2));
+            errors[0].message.clone()
+        }
+        ref other => panic!("expected compile error, got {:?}", other),
+    };
+    assert!(vm.report(&error).plain().starts_with(&format!(
+        "error: {}\n --> broken:2\n  |\n2 | var b = )\n  |\n",
+        message
+    )));
+
+    assert_eq!(
+        vm.report(&WrenError::InvalidHandle).plain(),
+        "error: handle does not belong to this VM\n"
+    );
+}
+
+#[test]
+fn ansi_matches_plain() {
+    let (vm, error) = vm();
+    let ansi = vm.report(&error).ansi();
+    assert!(ansi.starts_with("\x1b[1;31merror\x1b[0m"));
+
+    let mut stripped = String::new();
+    let mut rest = ansi.as_str();
+    while let Some(start) = rest.find('\x1b') {
+        stripped.push_str(&rest[..start]);
+        rest = &rest[start + rest[start..].find('m').unwrap() + 1..];
+    }
+    stripped.push_str(rest);
+    assert_eq!(stripped, vm.report(&error).plain());
+}
+
+#[test]
+fn json() {
+    let (vm, error) = vm();
+    let (fail, script) = functions(&error);
+    assert_eq!(
+        vm.report(&error).json(),
+        format!(
+            concat!(
+                r#"{{"kind":"runtime","message":"nope\n    at {0} (util:2)\n    at {1} (main:10)","#,
+                r#""panicked":false,"stack":["#,
+                r#"{{"function":"{0}","module":"util","file":"util","line":2,"generated":false,"#,
+                r#""source":"  static fail() {{ Fiber.abort(\"nope\") }}"}},"#,
+                r#"{{"function":"{1}","module":"main","file":"main","line":10,"generated":false,"#,
+                r#""source":"Util.fail()"}}]}}"#
+            ),
+            fail, script
+        )
+    );
+    assert_eq!(
+        vm.report(&WrenError::Conversion("expected Num".into())).json(),
+        r#"{"kind":"conversion","message":"expected Num"}"#
+    );
+}
+
+#[derive(WrenClass)]
+#[wren(module = "shapes", construct = "new(_)")]
+struct Circle;
+
+impl WrenConstruct for Circle {
+    fn construct(_: &mut Slots) -> Result<Self, String> {
+        Err("no circles".into())
+    }
+}
+
+#[test]
+fn generated_lines() {
+    let mut vm = WrenBuilder::new()
+        .bind_class::<Circle>()
+        .module_loader(|name: &str| match name {
+            "shapes" => Some("var Unit = 1\n".to_string()),
+            _ => None,
+        })
+        .build();
+    let error = vm.interpret("import \"shapes\" for Circle\nCircle.new(1)")
+        .unwrap_err();
+    let (line, function) = match error {
+        WrenError::Runtime { ref stack, .. } => {
+            assert_eq!(stack[0].module, "shapes");
+            assert!(vm.modules().get("shapes").unwrap().is_generated(stack[0].line));
+            (stack[0].line, stack[0].function.clone())
+        }
+        ref other => panic!("expected runtime error, got {:?}", other),
+    };
+
+    // The constructor is declared in generated code, not in the loaded
+    // source, so none of that is quoted.
+    let plain = vm.report(&error).plain();
+    assert!(plain.contains(&format!(
+        "--> shapes:{} (generated) in {}\n",
+        line, function
+    )));
+    assert!(!plain.contains("var Unit"));
+    assert!(vm.report(&error).json().contains(&format!(
+        r#""module":"shapes","file":"shapes","line":{},"generated":true,"source":null"#,
+        line
+    )));
+}
@@ -0,0 +1,123 @@
+#![cfg(feature = "serde")]
+
+#[macro_use]
+extern crate serde_derive;
+extern crate thrush;
+
+use std::collections::BTreeMap;
+
+use thrush::errors::WrenError;
+use thrush::output::CapturedOutput;
+use thrush::vm::*;
+use thrush::{from_wren, to_wren, Value};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+enum Difficulty {
+    Easy,
+    Custom { lives: u8 },
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Save {
+    player: String,
+    level: u32,
+    position: (f64, f64),
+    inventory: Vec<String>,
+    flags: BTreeMap<String, bool>,
+    difficulty: Difficulty,
+    checkpoint: Option<u32>,
+}
+
+const SCRIPT: &'static str = r##"
+class Game {
+    static describe(save) {
+        return "%(save["player"]) at level %(save["level"]) with %(save["inventory"].count) items"
+    }
+
+    static levelUp(save) {
+        save["level"] = save["level"] + 1
+        save["flags"]["cheated"] = false
+        save["difficulty"] = {"Custom": {"lives": 3}}
+        save["checkpoint"] = 2
+        return save
+    }
+}
+"##;
+
+fn save() -> Save {
+    let mut flags = BTreeMap::new();
+    flags.insert("tutorial".to_string(), true);
+    Save {
+        player: "thrush".into(),
+        level: 1,
+        position: (0.5, -2.0),
+        inventory: vec!["seed".into(), "worm".into()],
+        flags,
+        difficulty: Difficulty::Easy,
+        checkpoint: None,
+    }
+}
+
+#[test]
+fn round_trip() {
+    let output = CapturedOutput::new();
+    let mut vm = WrenBuilder::new().write_fn(output.writer()).build();
+    vm.interpret(SCRIPT).unwrap();
+    let game = vm.get_class("main", "Game").unwrap();
+    let describe = vm.make_call("describe(_)").unwrap();
+    let level_up = vm.make_call("levelUp(_)").unwrap();
+
+    let data = to_wren(&mut vm, &save()).unwrap();
+    let text: String = vm.call(&game, &describe, (&data,)).unwrap();
+    assert_eq!(text, "thrush at level 1 with 2 items");
+
+    let result: Handle = vm.call(&game, &level_up, (data,)).unwrap();
+    let loaded: Save = from_wren(&mut vm, &result).unwrap();
+
+    let mut expected = save();
+    expected.level = 2;
+    expected.flags.insert("cheated".into(), false);
+    expected.difficulty = Difficulty::Custom { lives: 3 };
+    expected.checkpoint = Some(2);
+    assert_eq!(loaded, expected);
+}
+
+#[test]
+fn primitives_and_errors() {
+    let mut vm = WrenBuilder::new().build();
+    match to_wren(&mut vm, &vec![1u8, 2, 3]).unwrap() {
+        Value::List(ref items) => assert_eq!(items.len(), 3),
+        other => panic!("unexpected value {:?}", other),
+    }
+    let n: u8 = from_wren(&mut vm, 255.0).unwrap();
+    assert_eq!(n, 255);
+
+    match from_wren::<u8, _>(&mut vm, 256.0) {
+        Err(WrenError::Conversion(_)) => {}
+        other => panic!("unexpected result {:?}", other),
+    }
+
+    // Past 2^53 integers would be rounded, as with the slot conversions.
+    let exact = 1u64 << 53;
+    let value = to_wren(&mut vm, &exact).unwrap();
+    let n: u64 = from_wren(&mut vm, &value).unwrap();
+    assert_eq!(n, exact);
+    for inexact in &[exact + 1, u64::MAX] {
+        match to_wren(&mut vm, inexact) {
+            Err(WrenError::Conversion(_)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+    match to_wren(&mut vm, &-(exact as i64 + 1)) {
+        Err(WrenError::Conversion(_)) => {}
+        other => panic!("unexpected result {:?}", other),
+    }
+    match from_wren::<i64, _>(&mut vm, 1e300) {
+        Err(WrenError::Conversion(_)) => {}
+        other => panic!("unexpected result {:?}", other),
+    }
+    match from_wren::<Save, _>(&mut vm, "not a save") {
+        Err(WrenError::Conversion(_)) => {}
+        other => panic!("unexpected result {:?}", other),
+    }
+}
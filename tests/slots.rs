@@ -0,0 +1,66 @@
+#[macro_use]
+extern crate thrush;
+
+use thrush::foreign::{ForeignMethod, WrenClass};
+use thrush::output::CapturedOutput;
+use thrush::vm::*;
+
+#[derive(Default, WrenClass)]
+#[wren(default)]
+struct Counter(f64);
+
+const INCREMENT: ForeignMethod = wren_fn!(slots, (counter: [Counter], by: f64) -> f64 {
+    counter.0 += by;
+    counter.0
+});
+
+// The body has the slots of the call as well, here to look at the receiver.
+const RECEIVER: ForeignMethod = wren_fn!(slots, None, (_) -> String {
+    format!("{:?}", slots.slot_type(0).unwrap())
+});
+
+const SCRIPT: &'static str = r##"
+foreign class Counter {
+    construct new() {}
+    foreign increment(by)
+    foreign receiver
+}
+
+class Math {
+    foreign static add(a, b)
+}
+"##;
+
+fn add() -> ForeignMethod {
+    ForeignMethod::new("(_,_)", |slots| {
+        let a: f64 = slots.get(1)?;
+        let b: f64 = slots.get(2)?;
+        slots.set(0, a + b)
+    })
+}
+
+#[test]
+fn safe_bindings() {
+    let output = CapturedOutput::new();
+    let mut vm = WrenBuilder::new()
+        .write_fn(output.writer())
+        .bind_class::<Counter>()
+        .bind_method("main", "Counter", false, "increment", INCREMENT)
+        .bind_method("main", "Counter", false, "receiver", RECEIVER)
+        .bind_method("main", "Math", true, "add", add())
+        .build();
+    vm.interpret(SCRIPT).unwrap();
+    vm.interpret(
+        r##"
+var counter = Counter.new()
+counter.increment(2)
+System.print(counter.increment(3))
+System.print(counter.receiver)
+System.print(Math.add(1, 2))
+"##,
+    ).unwrap();
+    assert_eq!(output.contents(), "5\nWREN_TYPE_FOREIGN\n3\n");
+
+    let error = vm.interpret("Math.add(1, \"2\")").unwrap_err();
+    assert!(error.to_string().starts_with("expecting f64 for slot 2"));
+}
@@ -0,0 +1,69 @@
+#[macro_use]
+extern crate thrush;
+
+use thrush::Value;
+use thrush::foreign::ForeignMethod;
+use thrush::output::CapturedOutput;
+use thrush::vm::*;
+
+fn describe(value: &Value) -> String {
+    match *value {
+        Value::List(ref values) => {
+            let inner: Vec<_> = values.iter().map(describe).collect();
+            format!("List[{}]", inner.join(", "))
+        }
+        ref other => other.type_name().to_string(),
+    }
+}
+
+const DESCRIBE: ForeignMethod = wren_fn!(vm, (_, value: Value) -> String {
+    describe(&value)
+});
+
+const ECHO: ForeignMethod = wren_fn!(vm, (_, value: Value) -> Value {
+    value
+});
+
+const FIRST_STRING: ForeignMethod = wren_fn!(vm, (_, values: Vec<Value>) -> Value {
+    values
+        .into_iter()
+        .find(|v| matches!(*v, Value::String(_)))
+        .unwrap_or(Value::Null)
+});
+
+const SCRIPT: &'static str = r##"
+class Dynamic {
+    foreign static describe(value)
+    foreign static echo(value)
+    foreign static firstString(values)
+}
+
+class Point {}
+"##;
+
+#[test]
+fn dynamic_values() {
+    let output = CapturedOutput::new();
+    let mut vm = WrenBuilder::new()
+        .write_fn(output.writer())
+        .bind_method("main", "Dynamic", true, "describe", DESCRIBE)
+        .bind_method("main", "Dynamic", true, "echo", ECHO)
+        .bind_method("main", "Dynamic", true, "firstString", FIRST_STRING)
+        .build();
+    vm.interpret(SCRIPT).unwrap();
+    vm.interpret(
+        r##"
+System.print(Dynamic.describe(null))
+System.print(Dynamic.describe([1, true, "s", [null], Point.new()]))
+System.print(Dynamic.echo([1, [false, "two"], null]))
+var p = Point.new()
+System.print(Dynamic.echo(p) == p)
+System.print(Dynamic.firstString([1, "first", "second"]))
+System.print(Dynamic.firstString([]))
+"##,
+    ).unwrap();
+    assert_eq!(
+        output.contents(),
+        "Null\nList[Num, Bool, String, List[Null], object]\n[1, [false, two], null]\ntrue\nfirst\nnull\n"
+    );
+}
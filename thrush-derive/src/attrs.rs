@@ -0,0 +1,54 @@
+use proc_macro2::Span;
+use syn::spanned::Spanned;
+use syn::{Attribute, Error, Lit, Meta, NestedMeta};
+
+/// One `key` or `key = "value"` entry of a `#[wren(...)]` attribute.
+pub struct WrenAttr {
+    pub key: String,
+    pub value: Option<String>,
+    pub span: Span,
+}
+
+impl WrenAttr {
+    pub fn unknown(&self) -> Error {
+        Error::new(self.span, format!("unknown wren attribute `{}`", self.key))
+    }
+}
+
+pub fn is_wren_attr(attr: &Attribute) -> bool {
+    attr.path.is_ident("wren")
+}
+
+// Collects the entries of every `#[wren(...)]` attribute.
+pub fn wren_attrs(attrs: &[Attribute]) -> Result<Vec<WrenAttr>, Error> {
+    let mut entries = vec![];
+    for attr in attrs.iter().filter(|a| is_wren_attr(a)) {
+        let list = match attr.parse_meta()? {
+            Meta::List(list) => list,
+            meta => return Err(Error::new_spanned(meta, "expected #[wren(...)]")),
+        };
+        for nested in list.nested {
+            let span = nested.span();
+            let entry = match nested {
+                NestedMeta::Meta(Meta::Path(ref path)) if path.get_ident().is_some() => WrenAttr {
+                    key: path.get_ident().unwrap().to_string(),
+                    value: None,
+                    span,
+                },
+                NestedMeta::Meta(Meta::NameValue(ref pair)) => {
+                    match (pair.path.get_ident(), &pair.lit) {
+                        (Some(key), Lit::Str(value)) => WrenAttr {
+                            key: key.to_string(),
+                            value: Some(value.value()),
+                            span,
+                        },
+                        _ => return Err(Error::new(span, "expected key = \"value\"")),
+                    }
+                }
+                _ => return Err(Error::new(span, "expected key or key = \"value\"")),
+            };
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
@@ -0,0 +1,50 @@
+use proc_macro2::TokenStream;
+use syn::{DeriveInput, Error};
+
+use attrs::wren_attrs;
+
+pub fn wren_class(input: &DeriveInput) -> Result<TokenStream, Error> {
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "foreign classes can't be generic, every instantiation would share a Wren class",
+        ));
+    }
+
+    let ident = &input.ident;
+    let mut module = "main".to_string();
+    let mut class = ident.to_string();
+    let mut constructors = vec![];
+    let mut default = false;
+    for attr in wren_attrs(&input.attrs)? {
+        match (attr.key.as_str(), attr.value.clone()) {
+            ("module", Some(value)) => module = value,
+            ("class", Some(value)) => class = value,
+            ("construct", Some(value)) => constructors.push(value),
+            ("default", None) => default = true,
+            _ => return Err(attr.unknown()),
+        }
+    }
+
+    // Leave the trait's `new()` default alone unless told otherwise.
+    let constructors = if constructors.is_empty() {
+        quote!()
+    } else {
+        quote!(const CONSTRUCTORS: &'static [&'static str] = &[#(#constructors),*];)
+    };
+
+    let default = if default {
+        quote!(impl ::thrush::foreign::DefaultConstruct for #ident {})
+    } else {
+        quote!()
+    };
+
+    Ok(quote! {
+        impl ::thrush::foreign::WrenClass for #ident {
+            const MODULE: &'static str = #module;
+            const CLASS: &'static str = #class;
+            #constructors
+        }
+        #default
+    })
+}
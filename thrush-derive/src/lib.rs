@@ -0,0 +1,52 @@
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+#[macro_use]
+extern crate syn;
+
+mod attrs;
+mod class;
+mod methods;
+
+use proc_macro::TokenStream;
+use syn::{DeriveInput, ItemImpl};
+
+/// Implements `thrush::foreign::WrenClass`.
+///
+/// The Wren side defaults to a class of the same name in the `main` module,
+/// and can be changed with `#[wren(module = "...", class = "...")]`. The
+/// constructors declared in the generated Wren source default to `new()`,
+/// each `#[wren(construct = "new(_)")]` declares one instead.
+/// `#[wren(default)]` builds objects with `Default::default()` by
+/// implementing `thrush::foreign::DefaultConstruct`.
+#[proc_macro_derive(WrenClass, attributes(wren))]
+pub fn derive_wren_class(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match class::wren_class(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Binds every method of an `impl` block as a Wren foreign method and
+/// implements `thrush::foreign::WrenMethods` so the whole class can be
+/// registered with `WrenBuilder::bind`.
+///
+/// Methods taking `&self` or `&mut self` become instance methods, the rest
+/// become static methods. Parameters are converted with `FromSlot`, except
+/// for references to other foreign classes, and the return value with
+/// `IntoSlot`. Individual methods can be tweaked with:
+///
+/// * `#[wren(name = "...")]` to use a different Wren name
+/// * `#[wren(getter)]`, `#[wren(setter)]`, `#[wren(subscript)]` or
+///   `#[wren(subscript_setter)]` for the other kinds of signatures
+/// * `#[wren(skip)]` to leave the method out
+#[proc_macro_attribute]
+pub fn methods(_args: TokenStream, input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as ItemImpl);
+    match methods::wren_methods(input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
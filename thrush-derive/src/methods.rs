@@ -0,0 +1,216 @@
+use proc_macro2::{Group, Span, TokenStream, TokenTree};
+use quote::ToTokens;
+use syn::{Error, FnArg, Ident, ImplItem, ImplItemMethod, ItemImpl, Type};
+
+use attrs::{is_wren_attr, wren_attrs};
+
+enum Kind {
+    Method,
+    Getter,
+    Setter,
+    Subscript,
+    SubscriptSetter,
+}
+
+pub fn wren_methods(mut input: ItemImpl) -> Result<TokenStream, Error> {
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "foreign class methods can't be generic",
+        ));
+    }
+    if let Some((_, ref path, _)) = input.trait_ {
+        return Err(Error::new_spanned(
+            path,
+            "#[thrush::methods] goes on an inherent impl block",
+        ));
+    }
+
+    let self_ty = input.self_ty.clone();
+    let mut bound = vec![];
+    for item in input.items.iter_mut() {
+        if let ImplItem::Method(ref mut method) = *item {
+            let attrs = wren_attrs(&method.attrs)?;
+            method.attrs.retain(|attr| !is_wren_attr(attr));
+            if attrs.iter().any(|attr| attr.key == "skip") {
+                continue;
+            }
+            bound.push(bind_method(&self_ty, method, attrs)?);
+        }
+    }
+
+    Ok(quote! {
+        #input
+
+        impl ::thrush::foreign::WrenMethods for #self_ty {
+            fn methods() -> ::std::vec::Vec<::thrush::foreign::BoundMethod> {
+                vec![#(#bound),*]
+            }
+        }
+    })
+}
+
+fn bind_method(
+    self_ty: &Type,
+    method: &ImplItemMethod,
+    attrs: Vec<::attrs::WrenAttr>,
+) -> Result<TokenStream, Error> {
+    let sig = &method.sig;
+    if !sig.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &sig.generics,
+            "foreign methods can't be generic",
+        ));
+    }
+
+    let mut name = sig.ident.to_string().trim_start_matches("r#").to_string();
+    let mut kind = Kind::Method;
+    for attr in attrs {
+        match (attr.key.as_str(), attr.value.clone()) {
+            ("name", Some(value)) => name = value,
+            ("getter", None) => kind = Kind::Getter,
+            ("setter", None) => kind = Kind::Setter,
+            ("subscript", None) => kind = Kind::Subscript,
+            ("subscript_setter", None) => kind = Kind::SubscriptSetter,
+            _ => return Err(attr.unknown()),
+        }
+    }
+
+    let mut receiver = None;
+    let mut conversions = vec![];
+    let mut args = vec![];
+    for input in sig.inputs.iter() {
+        let pat = match *input {
+            FnArg::Receiver(ref recv) => {
+                if recv.reference.is_none() {
+                    return Err(Error::new_spanned(
+                        recv,
+                        "foreign methods take self by reference",
+                    ));
+                }
+                receiver = Some(if recv.mutability.is_some() {
+                    (quote!(let mut this = slots.borrow_mut::<#self_ty>(0)?;), quote!(&mut *this))
+                } else {
+                    (quote!(let this = slots.borrow::<#self_ty>(0)?;), quote!(&*this))
+                });
+                continue;
+            }
+            FnArg::Typed(ref pat) => pat,
+        };
+
+        let slot = args.len() + 1;
+        let var = Ident::new(&format!("arg{}", args.len()), Span::call_site());
+        // The conversions end up in a nested function, where `Self` means
+        // nothing.
+        let ty: Type = syn::parse2(replace_self(pat.ty.to_token_stream(), self_ty))?;
+        match ty {
+            Type::Reference(ref r) if is_str(&r.elem) => {
+                conversions.push(quote!(let #var: ::std::string::String = slots.get(#slot)?;));
+                args.push(quote!(&#var));
+            }
+            Type::Reference(ref r) => {
+                let elem = &r.elem;
+                if r.mutability.is_some() {
+                    conversions.push(quote!(let mut #var = slots.borrow_mut::<#elem>(#slot)?;));
+                    args.push(quote!(&mut *#var));
+                } else {
+                    conversions.push(quote!(let #var = slots.borrow::<#elem>(#slot)?;));
+                    args.push(quote!(&*#var));
+                }
+            }
+            ref ty => {
+                conversions.push(quote!(let #var: #ty = slots.get(#slot)?;));
+                args.push(quote!(#var));
+            }
+        }
+    }
+
+    let arity = args.len();
+    let params = |n: usize| vec!["_"; n].join(",");
+    let signature = match kind {
+        Kind::Method => format!("({})", params(arity)),
+        Kind::Getter if arity == 0 => String::new(),
+        Kind::Setter if arity == 1 => {
+            name.push('=');
+            "(_)".to_string()
+        }
+        Kind::Subscript if arity >= 1 => {
+            name.clear();
+            format!("[{}]", params(arity))
+        }
+        Kind::SubscriptSetter if arity >= 2 => {
+            name.clear();
+            format!("[{}]=(_)", params(arity - 1))
+        }
+        _ => {
+            return Err(Error::new_spanned(
+                sig,
+                "wrong number of parameters for this kind of method",
+            ))
+        }
+    };
+
+    let is_static = receiver.is_none();
+    let ident = &sig.ident;
+    let (receiver, call) = match receiver {
+        Some((binding, this)) => (binding, quote!(<#self_ty>::#ident(#this, #(#args),*))),
+        None => (quote!(), quote!(<#self_ty>::#ident(#(#args),*))),
+    };
+
+    Ok(quote! {
+        ::thrush::foreign::BoundMethod {
+            is_static: #is_static,
+            name: #name,
+            method: ::thrush::foreign::ForeignMethod {
+                signature: #signature,
+                method: {
+                    unsafe extern "C" fn trampoline(vm: *mut ::thrush::sys::WrenVM) {
+                        let body = |slots: &mut ::thrush::foreign::Slots| {
+                            let result: ::std::result::Result<(), ::std::string::String> = (|| {
+                                // Release the borrows before slot 0 is
+                                // overwritten, it may be the only reference
+                                // to the receiver.
+                                let ret = {
+                                    #receiver
+                                    #(#conversions)*
+                                    #call
+                                };
+                                slots.set(0, ret)
+                            })();
+                            if let ::std::result::Result::Err(message) = result {
+                                slots.abort(&message);
+                            }
+                        };
+                        unsafe { ::thrush::foreign::with_slots(vm, body) };
+                    }
+                    trampoline
+                },
+            },
+        }
+    })
+}
+
+fn replace_self(tokens: TokenStream, self_ty: &Type) -> TokenStream {
+    tokens
+        .into_iter()
+        .flat_map(|tree| match tree {
+            TokenTree::Ident(ref ident) if ident == "Self" => self_ty.to_token_stream(),
+            TokenTree::Group(ref group) => {
+                let mut replaced = Group::new(
+                    group.delimiter(),
+                    replace_self(group.stream(), self_ty),
+                );
+                replaced.set_span(group.span());
+                TokenTree::Group(replaced).into()
+            }
+            tree => tree.into(),
+        })
+        .collect()
+}
+
+fn is_str(ty: &Type) -> bool {
+    match *ty {
+        Type::Path(ref path) => path.qself.is_none() && path.path.is_ident("str"),
+        _ => false,
+    }
+}
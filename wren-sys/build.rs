@@ -15,6 +15,30 @@ fn main() {
             .expect("failed to run 'make'")
             .success()
     );
+    // The shim calls into Wren, so it has to come first when linking.
+    let out_dir = env::var("OUT_DIR").unwrap();
+    let object = format!("{}/shim.o", out_dir);
+    let cc = env::var("CC").unwrap_or_else(|_| "cc".into());
+    assert!(
+        Command::new(cc)
+            .args(&["-c", "-O2", "-fPIC", "-o", &object])
+            .arg(format!("-I{}/wren/src/include", top_dir))
+            .arg(format!("{}/src/shim.c", top_dir))
+            .status()
+            .expect("failed to run the C compiler")
+            .success()
+    );
+    assert!(
+        Command::new("ar")
+            .args(&["crs", &format!("{}/libwrensys_shim.a", out_dir), &object])
+            .status()
+            .expect("failed to run 'ar'")
+            .success()
+    );
+    println!("cargo:rustc-link-search=native={}", out_dir);
+    println!("cargo:rustc-link-lib=static=wrensys_shim");
+    println!("cargo:rerun-if-changed=src/shim.c");
+
     println!("cargo:rustc-link-search=native={}/wren/lib", top_dir);
     println!("cargo:rustc-link-lib=static=wren");
 
@@ -2,4 +2,27 @@
 #![allow(non_camel_case_types)]
 #![allow(non_snake_case)]
 
-include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
\ No newline at end of file
+include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+
+// From src/shim.c, for stopping a VM in the middle of running a script.
+extern "C" {
+    /// Like `wrenInterpret`, but returns 0 without setting `result` if
+    /// `wrensys_escape` is called before it finishes, and 1 otherwise.
+    pub fn wrensys_protected_interpret(
+        vm: *mut WrenVM,
+        source: *const ::std::os::raw::c_char,
+        result: *mut WrenInterpretResult,
+    ) -> ::std::os::raw::c_int;
+
+    /// Like `wrenCall`, returning the same way as `wrensys_protected_interpret`.
+    pub fn wrensys_protected_call(
+        vm: *mut WrenVM,
+        method: *mut WrenHandle,
+        result: *mut WrenInterpretResult,
+    ) -> ::std::os::raw::c_int;
+
+    /// Jumps straight out of the innermost protected call on this thread,
+    /// skipping every frame in between. Those frames must not have anything
+    /// left to drop, and the VM it leaves can't run again.
+    pub fn wrensys_escape() -> !;
+}
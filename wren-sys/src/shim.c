@@ -0,0 +1,46 @@
+#include <setjmp.h>
+
+#include "wren.h"
+
+// Where wrensys_escape jumps to. Protected calls can nest when a foreign
+// method runs another VM, so each one restores the point around it.
+static __thread jmp_buf* escape_point = NULL;
+
+int wrensys_protected_interpret(WrenVM* vm, const char* source,
+                                WrenInterpretResult* result)
+{
+  jmp_buf here;
+  jmp_buf* outer = escape_point;
+  escape_point = &here;
+  if (setjmp(here) != 0)
+  {
+    escape_point = outer;
+    return 0;
+  }
+
+  *result = wrenInterpret(vm, source);
+  escape_point = outer;
+  return 1;
+}
+
+int wrensys_protected_call(WrenVM* vm, WrenHandle* method,
+                           WrenInterpretResult* result)
+{
+  jmp_buf here;
+  jmp_buf* outer = escape_point;
+  escape_point = &here;
+  if (setjmp(here) != 0)
+  {
+    escape_point = outer;
+    return 0;
+  }
+
+  *result = wrenCall(vm, method);
+  escape_point = outer;
+  return 1;
+}
+
+void wrensys_escape(void)
+{
+  longjmp(*escape_point, 1);
+}